@@ -1,14 +1,15 @@
-use crate::configs::ButtonsLayout;
+use crate::configs::{ActionTimeouts, ButtonActions, ButtonsLayout};
 use crate::match_event::ButtonName;
 use crate::utils::{get_or_default, get_or_err, Container};
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use color_eyre::eyre::Result;
 use serde::{Deserialize, Serialize};
+use std::time::Instant;
 use strum::IntoEnumIterator;
 use strum_macros::Display;
 use universal_input::{KeyCode, KeyCodes};
 
-#[derive(Display, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Display, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Command {
     Pressed(KeyCode),
     Released(KeyCode),
@@ -16,11 +17,31 @@ pub enum Command {
 
 pub type Commands = Vec<Command>;
 
+#[derive(Copy, Clone, Debug)]
+enum PendingButton {
+    // Waiting for the hold or long press time
+    Pressed(ButtonName, Instant),
+    // Waiting for the second press of a double tap
+    Released(ButtonName, Instant),
+}
+
 #[derive(Clone, Debug)]
 pub struct ButtonsState {
     pressed: AHashMap<KeyCode, bool>,
+    // Buttons pressed on the device. Analog triggers repeat their press events
+    held: AHashSet<ButtonName>,
     RESET_BTN: ButtonName,
     buttons_layout: AHashMap<ButtonName, KeyCodes>,
+    layer_switches: AHashMap<ButtonName, String>,
+    layers: AHashMap<String, AHashMap<ButtonName, KeyCodes>>,
+    // Held layer buttons. The last one has priority
+    active_layers: Vec<ButtonName>,
+    // Codes pressed by the button and the layer button they came from
+    pressed_via: AHashMap<ButtonName, (Option<ButtonName>, KeyCodes)>,
+    button_actions: AHashMap<ButtonName, ButtonActions>,
+    action_timeouts: ActionTimeouts,
+    // Button with actions that isn't resolved yet
+    pending: Option<PendingButton>,
     special_codes: Container<KeyCode>,
     special_buttons: Container<ButtonName>,
     repeat_keys: bool,
@@ -28,7 +49,11 @@ pub struct ButtonsState {
 }
 
 impl ButtonsState {
-    pub fn new(buttons_layout: ButtonsLayout, repeat_keys: bool) -> Self {
+    pub fn new(
+        buttons_layout: ButtonsLayout,
+        repeat_keys: bool,
+        action_timeouts: ActionTimeouts,
+    ) -> Self {
         let special_codes = Container::from(vec![
             KeyCode::None,
             KeyCode::RESET_BTN,
@@ -50,8 +75,16 @@ impl ButtonsState {
 
         Self {
             pressed,
+            held: AHashSet::new(),
             RESET_BTN: buttons_layout.reset_btn,
             buttons_layout: buttons_layout.layout,
+            layer_switches: buttons_layout.layer_switches,
+            layers: buttons_layout.layers,
+            active_layers: vec![],
+            pressed_via: AHashMap::new(),
+            button_actions: buttons_layout.button_actions,
+            action_timeouts,
+            pending: None,
             special_codes,
             special_buttons,
             repeat_keys,
@@ -91,15 +124,154 @@ impl ButtonsState {
         Ok(())
     }
 
+    // Falls through active layers to the base layout
+    fn layer_key_codes(&self, button_name: ButtonName) -> Result<(Option<ButtonName>, KeyCodes)> {
+        for layer_btn in self.active_layers.iter().rev() {
+            let key_codes = self
+                .layer_switches
+                .get(layer_btn)
+                .and_then(|layer_name| self.layers.get(layer_name))
+                .and_then(|layer| layer.get(&button_name));
+            if let Some(key_codes) = key_codes {
+                return Ok((Some(*layer_btn), key_codes.clone()));
+            }
+        }
+        let key_codes = get_or_err(&self.buttons_layout, &button_name)?;
+        Ok((None, key_codes.clone()))
+    }
+
+    fn tap_keycodes(&mut self, key_codes: KeyCodes) -> Result<()> {
+        self.press_keycodes(key_codes.clone(), true)?;
+        self.release_keycodes(key_codes, true)
+    }
+
+    // Pressed button becomes held, released one a single tap
+    fn resolve_pending(&mut self) -> Result<()> {
+        match self.pending.take() {
+            None => Ok(()),
+            Some(PendingButton::Pressed(button_name, _)) => {
+                let key_codes = match &get_or_err(&self.button_actions, &button_name)?.hold {
+                    Some(hold) => hold.key_codes.clone(),
+                    None => get_or_err(&self.buttons_layout, &button_name)?.clone(),
+                };
+                self.press_keycodes(key_codes.clone(), false)?;
+                self.pressed_via.insert(button_name, (None, key_codes));
+                Ok(())
+            }
+            Some(PendingButton::Released(button_name, _)) => {
+                let key_codes = get_or_err(&self.buttons_layout, &button_name)?.clone();
+                self.tap_keycodes(key_codes)
+            }
+        }
+    }
+
+    /// Resolves the pending button once its time is up
+    pub fn update_actions(&mut self) -> Result<()> {
+        let timeouts = self.action_timeouts;
+        match self.pending {
+            None => Ok(()),
+            Some(PendingButton::Pressed(button_name, pressed_at)) => {
+                let actions = get_or_err(&self.button_actions, &button_name)?.clone();
+                let elapsed = pressed_at.elapsed();
+                // Held past the double tap time, it presses its codes as a plain button
+                let is_held_past_double_tap = actions.hold.is_none()
+                    && actions.long_press.is_none()
+                    && actions.double_tap.as_ref().is_some_and(|double_tap| {
+                        elapsed >= double_tap.timeout.unwrap_or(timeouts.double_tap)
+                    });
+                let is_hold = is_held_past_double_tap
+                    || actions
+                        .hold
+                        .is_some_and(|hold| elapsed >= hold.timeout.unwrap_or(timeouts.hold));
+                let long_press = actions.long_press.filter(|long_press| {
+                    elapsed >= long_press.timeout.unwrap_or(timeouts.long_press)
+                });
+                if is_hold {
+                    self.resolve_pending()
+                } else if let Some(long_press) = long_press {
+                    // Its release does nothing
+                    self.pending = None;
+                    self.tap_keycodes(long_press.key_codes)
+                } else {
+                    Ok(())
+                }
+            }
+            Some(PendingButton::Released(button_name, released_at)) => {
+                let double_tap_timeout = get_or_err(&self.button_actions, &button_name)?
+                    .double_tap
+                    .as_ref()
+                    .and_then(|double_tap| double_tap.timeout)
+                    .unwrap_or(timeouts.double_tap);
+                match released_at.elapsed() >= double_tap_timeout {
+                    true => self.resolve_pending(),
+                    false => Ok(()),
+                }
+            }
+        }
+    }
+
     #[inline]
     pub fn press(&mut self, button_name: ButtonName, always_press: bool) -> Result<()> {
-        if self.special_buttons.contains(&button_name) {
+        if self.special_buttons.contains(&button_name) || self.held.contains(&button_name) {
             return Ok(());
         }
+        self.press_raw(button_name, always_press)?;
+        // After pressing, as Esc clears the held buttons too
+        self.held.insert(button_name);
+        Ok(())
+    }
 
-        let key_codes = get_or_err(&self.buttons_layout, &button_name)?;
+    fn press_raw(&mut self, button_name: ButtonName, always_press: bool) -> Result<()> {
+        match self.pending {
+            Some(PendingButton::Released(pending_btn, _)) if pending_btn == button_name => {
+                self.pending = None;
+                let key_codes = get_or_err(&self.button_actions, &button_name)?
+                    .double_tap
+                    .as_ref()
+                    .map(|double_tap| double_tap.key_codes.clone())
+                    .unwrap_or_default();
+                // Its release does nothing
+                return self.tap_keycodes(key_codes);
+            }
+            // Another button interrupts the pending one, as in keyboard firmware
+            _ => self.resolve_pending()?,
+        }
+        if self.layer_switches.contains_key(&button_name) {
+            if !self.active_layers.contains(&button_name) {
+                self.active_layers.push(button_name);
+            }
+            return Ok(());
+        }
+
+        let (layer_btn, key_codes) = self.layer_key_codes(button_name)?;
+        // Waits until it's clear which action happened. Plain buttons are pressed right away
+        if layer_btn.is_none() && self.button_actions.contains_key(&button_name) {
+            self.pending = Some(PendingButton::Pressed(button_name, Instant::now()));
+            return Ok(());
+        }
         self.press_keycodes(key_codes.clone(), always_press)?;
+        // After pressing, as Esc clears everything
+        self.pressed_via.insert(button_name, (layer_btn, key_codes));
+
+        Ok(())
+    }
+
+    // Keys pressed through the layer are released with it
+    fn release_layer(&mut self, layer_btn: ButtonName) -> Result<()> {
+        self.active_layers
+            .retain(|active_btn| *active_btn != layer_btn);
 
+        let pressed_via_layer: Vec<ButtonName> = self
+            .pressed_via
+            .iter()
+            .filter(|(_, (via_btn, _))| *via_btn == Some(layer_btn))
+            .map(|(button_name, _)| *button_name)
+            .collect();
+        for button_name in pressed_via_layer {
+            if let Some((_, key_codes)) = self.pressed_via.remove(&button_name) {
+                self.release_keycodes(key_codes, false)?;
+            }
+        }
         Ok(())
     }
 
@@ -108,9 +280,32 @@ impl ButtonsState {
         if self.special_buttons.contains(&button_name) {
             return Ok(());
         }
+        self.held.remove(&button_name);
+        if self.layer_switches.contains_key(&button_name) {
+            return self.release_layer(button_name);
+        }
+        // Released before any other action happened
+        let is_pending = matches!(
+            self.pending,
+            Some(PendingButton::Pressed(pending_btn, _)) if pending_btn == button_name
+        );
+        if is_pending {
+            let has_double_tap = get_or_err(&self.button_actions, &button_name)?
+                .double_tap
+                .is_some();
+            if has_double_tap {
+                self.pending = Some(PendingButton::Released(button_name, Instant::now()));
+                return Ok(());
+            }
+            self.pending = None;
+            let key_codes = get_or_err(&self.buttons_layout, &button_name)?.clone();
+            return self.tap_keycodes(key_codes);
+        }
 
-        let key_codes = get_or_err(&self.buttons_layout, &button_name)?;
-        self.release_keycodes(key_codes.clone(), false)?;
+        // Released already if its layer went first
+        if let Some((_, key_codes)) = self.pressed_via.remove(&button_name) {
+            self.release_keycodes(key_codes, false)?;
+        }
 
         Ok(())
     }
@@ -120,6 +315,10 @@ impl ButtonsState {
         for key_code in self.pressed.clone().keys() {
             self.release_keycodes(vec![*key_code], always_release)?;
         }
+        self.held.clear();
+        self.active_layers.clear();
+        self.pressed_via.clear();
+        self.pending = None;
         Ok(())
     }
 
@@ -143,3 +342,249 @@ impl ButtonsState {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Command::{Pressed, Released};
+    use super::*;
+    use crate::configs::ButtonsLayoutRaw;
+    use std::collections::BTreeMap;
+    use std::mem::take;
+    use std::time::Duration;
+
+    const NEVER: Duration = Duration::from_secs(3600);
+
+    const LAYOUT: &str = r#"
+BtnDown_SideR: [ "KEY_SPACE" ]
+Wing_SideR: [ "LAYER(hotbar)" ]
+BtnUp_SideR: { tap: [ "KEY_Q" ], hold: [ "KEY_LEFTSHIFT" ] }
+LowerTriggerAsBtn_SideR: { tap: [ "MOUSE_LEFT" ], hold: [ "MOUSE_RIGHT" ] }
+BtnLeft_SideR: { tap: [ "KEY_E" ], double_tap: [ "KEY_R" ] }
+BtnRight_SideR: { tap: [ "KEY_F" ], long_press: [ "KEY_G" ] }
+"#;
+
+    const LAYERS: &str = r#"
+hotbar:
+  BtnDown_SideR: [ "KEY_1" ]
+"#;
+
+    fn new_state(hold: Duration, long_press: Duration, double_tap: Duration) -> ButtonsState {
+        let layout_raw: ButtonsLayoutRaw = serde_yml::from_str(LAYOUT).unwrap();
+        let layers_raw: BTreeMap<String, ButtonsLayoutRaw> = serde_yml::from_str(LAYERS).unwrap();
+        let mut buttons_layout = ButtonsLayout::load(layout_raw, true).unwrap();
+        buttons_layout.load_layers(&layers_raw).unwrap();
+        let action_timeouts = ActionTimeouts {
+            hold,
+            long_press,
+            double_tap,
+        };
+        ButtonsState::new(buttons_layout, false, action_timeouts)
+    }
+
+    fn take_queue(buttons_state: &mut ButtonsState) -> Commands {
+        take(&mut buttons_state.queue)
+    }
+
+    #[test]
+    fn tap_before_hold_time() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::BtnUp_SideR, false)?;
+        buttons_state.update_actions()?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        buttons_state.release(ButtonName::BtnUp_SideR)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_Q), Released(KeyCode::KEY_Q)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hold_after_hold_time() -> Result<()> {
+        let mut buttons_state = new_state(Duration::ZERO, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::BtnUp_SideR, false)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+        buttons_state.update_actions()?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_LEFTSHIFT)]
+        );
+
+        buttons_state.release(ButtonName::BtnUp_SideR)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Released(KeyCode::KEY_LEFTSHIFT)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn other_button_interrupts_into_hold() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::BtnUp_SideR, false)?;
+        buttons_state.press(ButtonName::BtnDown_SideR, false)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_LEFTSHIFT), Pressed(KeyCode::KEY_SPACE)]
+        );
+
+        buttons_state.release(ButtonName::BtnDown_SideR)?;
+        buttons_state.release(ButtonName::BtnUp_SideR)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [
+                Released(KeyCode::KEY_SPACE),
+                Released(KeyCode::KEY_LEFTSHIFT)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_trigger_press_is_ignored() -> Result<()> {
+        let mut buttons_state = new_state(Duration::ZERO, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::LowerTriggerAsBtn_SideR, false)?;
+        buttons_state.update_actions()?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::MOUSE_RIGHT)]
+        );
+
+        // Analog trigger keeps sending presses while it moves
+        buttons_state.press(ButtonName::LowerTriggerAsBtn_SideR, false)?;
+        buttons_state.update_actions()?;
+        buttons_state.press(ButtonName::LowerTriggerAsBtn_SideR, false)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        buttons_state.release(ButtonName::LowerTriggerAsBtn_SideR)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Released(KeyCode::MOUSE_RIGHT)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn release_all_deactivates_layers() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::Wing_SideR, false)?;
+        buttons_state.press(ButtonName::BtnDown_SideR, false)?;
+        assert_eq!(take_queue(&mut buttons_state), [Pressed(KeyCode::KEY_1)]);
+
+        buttons_state.release_all_soft()?;
+        assert_eq!(take_queue(&mut buttons_state), [Released(KeyCode::KEY_1)]);
+
+        buttons_state.press(ButtonName::BtnDown_SideR, false)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_SPACE)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn double_tap_on_second_press() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::BtnLeft_SideR, false)?;
+        buttons_state.release(ButtonName::BtnLeft_SideR)?;
+        buttons_state.update_actions()?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        buttons_state.press(ButtonName::BtnLeft_SideR, false)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_R), Released(KeyCode::KEY_R)]
+        );
+
+        buttons_state.release(ButtonName::BtnLeft_SideR)?;
+        buttons_state.update_actions()?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn single_tap_waits_for_double_tap_time() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, Duration::ZERO);
+
+        buttons_state.press(ButtonName::BtnLeft_SideR, false)?;
+        buttons_state.release(ButtonName::BtnLeft_SideR)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        buttons_state.update_actions()?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_E), Released(KeyCode::KEY_E)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn other_button_taps_the_deferred_single_tap() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::BtnLeft_SideR, false)?;
+        buttons_state.release(ButtonName::BtnLeft_SideR)?;
+        buttons_state.press(ButtonName::BtnDown_SideR, false)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [
+                Pressed(KeyCode::KEY_E),
+                Released(KeyCode::KEY_E),
+                Pressed(KeyCode::KEY_SPACE)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn long_press_after_long_press_time() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, Duration::ZERO, NEVER);
+
+        buttons_state.press(ButtonName::BtnRight_SideR, false)?;
+        buttons_state.update_actions()?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_G), Released(KeyCode::KEY_G)]
+        );
+
+        buttons_state.release(ButtonName::BtnRight_SideR)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn short_press_is_a_tap() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, NEVER);
+
+        buttons_state.press(ButtonName::BtnRight_SideR, false)?;
+        buttons_state.update_actions()?;
+        buttons_state.release(ButtonName::BtnRight_SideR)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [Pressed(KeyCode::KEY_F), Released(KeyCode::KEY_F)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn double_tap_button_is_held_past_double_tap_time() -> Result<()> {
+        let mut buttons_state = new_state(NEVER, NEVER, Duration::ZERO);
+
+        buttons_state.press(ButtonName::BtnLeft_SideR, false)?;
+        buttons_state.update_actions()?;
+        assert_eq!(take_queue(&mut buttons_state), [Pressed(KeyCode::KEY_E)]);
+
+        buttons_state.update_actions()?;
+        buttons_state.release(ButtonName::BtnLeft_SideR)?;
+        buttons_state.update_actions()?;
+        assert_eq!(take_queue(&mut buttons_state), [Released(KeyCode::KEY_E)]);
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+use crate::buttons_state::ButtonsState;
+use crate::configs::ChordsConfigs;
+use crate::match_event::ButtonName;
+use ahash::AHashMap;
+use color_eyre::eyre::Result;
+use log::debug;
+use std::mem::take;
+use strum::EnumCount;
+use universal_input::KeyCodes;
+
+// Bit per button
+pub type Chord = u64;
+
+const _: () = assert!(
+    ButtonName::COUNT <= Chord::BITS as usize,
+    "ButtonName has more variants than Chord has bits"
+);
+
+pub fn chord_of(buttons: &[ButtonName]) -> Chord {
+    buttons
+        .iter()
+        .fold(0, |chord, button_name| chord | 1 << *button_name as u64)
+}
+
+/// Buttons pressed together type a dictionary entry once all of them are released
+pub struct ChordState {
+    chord_btns: Vec<ButtonName>,
+    dictionary: AHashMap<Chord, Vec<KeyCodes>>,
+    held: Chord,
+    chord: Chord,
+}
+
+impl ChordState {
+    pub fn new(chords_cfg: &ChordsConfigs) -> Self {
+        Self {
+            chord_btns: chords_cfg.chord_btns.clone(),
+            dictionary: chords_cfg.dictionary.clone(),
+            held: 0,
+            chord: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.held = 0;
+        self.chord = 0;
+    }
+
+    /// Returns false if the button isn't used in chords
+    pub fn press(&mut self, button_name: ButtonName) -> bool {
+        if !self.chord_btns.contains(&button_name) {
+            return false;
+        }
+        let button = chord_of(&[button_name]);
+        self.held |= button;
+        self.chord |= button;
+        true
+    }
+
+    /// Returns false if the button isn't used in chords
+    pub fn release(
+        &mut self,
+        button_name: ButtonName,
+        buttons_state: &mut ButtonsState,
+    ) -> Result<bool> {
+        if !self.chord_btns.contains(&button_name) {
+            return Ok(false);
+        }
+        self.held &= !chord_of(&[button_name]);
+        // Pressed before typing started
+        if self.held != 0 || self.chord == 0 {
+            return Ok(true);
+        }
+
+        let chord = take(&mut self.chord);
+        match self.dictionary.get(&chord) {
+            None => debug!("Unknown chord: {:#b}", chord),
+            Some(key_strokes) => {
+                for key_codes in key_strokes {
+                    buttons_state.press_keycodes(key_codes.clone(), true)?;
+                    buttons_state.release_keycodes(key_codes.clone(), true)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buttons_state::Command::{self, Pressed, Released};
+    use crate::configs::{ActionTimeouts, ButtonsLayout};
+    use std::path::Path;
+    use universal_input::KeyCode;
+
+    fn new_states() -> (ChordState, ButtonsState) {
+        let configs_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+        let chords_cfg = ChordsConfigs::load(configs_dir, "chords").unwrap();
+        let buttons_state =
+            ButtonsState::new(ButtonsLayout::default(), false, ActionTimeouts::default());
+        (ChordState::new(&chords_cfg), buttons_state)
+    }
+
+    fn take_queue(buttons_state: &mut ButtonsState) -> Vec<Command> {
+        take(&mut buttons_state.queue)
+    }
+
+    fn typed(key_codes: &[KeyCode]) -> Vec<Command> {
+        key_codes
+            .iter()
+            .flat_map(|key_code| [Pressed(*key_code), Released(*key_code)])
+            .collect()
+    }
+
+    #[test]
+    fn chord_is_typed_after_all_buttons_are_released() -> Result<()> {
+        let (mut chord_state, mut buttons_state) = new_states();
+
+        assert!(chord_state.press(ButtonName::BtnDown_SideR));
+        assert!(chord_state.press(ButtonName::BtnLeft_SideR));
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        assert!(chord_state.release(ButtonName::BtnDown_SideR, &mut buttons_state)?);
+        assert!(take_queue(&mut buttons_state).is_empty());
+        assert!(chord_state.release(ButtonName::BtnLeft_SideR, &mut buttons_state)?);
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            typed(&[
+                KeyCode::KEY_T,
+                KeyCode::KEY_H,
+                KeyCode::KEY_E,
+                KeyCode::KEY_SPACE
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn partial_release_keeps_the_chord() -> Result<()> {
+        let (mut chord_state, mut buttons_state) = new_states();
+
+        chord_state.press(ButtonName::BtnDown_SideR);
+        chord_state.press(ButtonName::BtnLeft_SideR);
+        chord_state.release(ButtonName::BtnLeft_SideR, &mut buttons_state)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        // Pressed again while the other button is still held
+        chord_state.press(ButtonName::BtnLeft_SideR);
+        chord_state.release(ButtonName::BtnLeft_SideR, &mut buttons_state)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        chord_state.release(ButtonName::BtnDown_SideR, &mut buttons_state)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            typed(&[
+                KeyCode::KEY_T,
+                KeyCode::KEY_H,
+                KeyCode::KEY_E,
+                KeyCode::KEY_SPACE
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keys_of_a_chord_are_pressed_together() -> Result<()> {
+        let (mut chord_state, mut buttons_state) = new_states();
+
+        chord_state.press(ButtonName::UpperTrigger_SideR);
+        chord_state.press(ButtonName::BtnRight_SideR);
+        chord_state.release(ButtonName::UpperTrigger_SideR, &mut buttons_state)?;
+        chord_state.release(ButtonName::BtnRight_SideR, &mut buttons_state)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            [
+                Pressed(KeyCode::KEY_LEFTCTRL),
+                Pressed(KeyCode::KEY_BACKSPACE),
+                Released(KeyCode::KEY_BACKSPACE),
+                Released(KeyCode::KEY_LEFTCTRL),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_chord_types_nothing() -> Result<()> {
+        let (mut chord_state, mut buttons_state) = new_states();
+
+        chord_state.press(ButtonName::Wing_SideL);
+        chord_state.press(ButtonName::Wing_SideR);
+        chord_state.release(ButtonName::Wing_SideL, &mut buttons_state)?;
+        chord_state.release(ButtonName::Wing_SideR, &mut buttons_state)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        // The next chord starts from scratch
+        chord_state.press(ButtonName::Wing_SideL);
+        chord_state.release(ButtonName::Wing_SideL, &mut buttons_state)?;
+        assert_eq!(take_queue(&mut buttons_state), typed(&[KeyCode::KEY_RIGHT]));
+        Ok(())
+    }
+
+    #[test]
+    fn reset_drops_the_chord() -> Result<()> {
+        let (mut chord_state, mut buttons_state) = new_states();
+
+        chord_state.press(ButtonName::BtnDown_SideR);
+        chord_state.press(ButtonName::BtnLeft_SideR);
+        // Switched out of Typing mode and back while the buttons were held
+        chord_state.reset();
+        chord_state.release(ButtonName::BtnDown_SideR, &mut buttons_state)?;
+        chord_state.release(ButtonName::BtnLeft_SideR, &mut buttons_state)?;
+        assert!(take_queue(&mut buttons_state).is_empty());
+
+        chord_state.press(ButtonName::BtnRight_SideR);
+        chord_state.release(ButtonName::BtnRight_SideR, &mut buttons_state)?;
+        assert_eq!(
+            take_queue(&mut buttons_state),
+            typed(&[KeyCode::KEY_BACKSPACE])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn other_buttons_are_not_chords() -> Result<()> {
+        let (mut chord_state, mut buttons_state) = new_states();
+
+        assert!(!chord_state.press(ButtonName::ExtraBtnCentral));
+        assert!(!chord_state.release(ButtonName::ExtraBtnCentral, &mut buttons_state)?);
+        assert!(take_queue(&mut buttons_state).is_empty());
+        Ok(())
+    }
+}
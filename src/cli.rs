@@ -0,0 +1,85 @@
+use crate::configs::InputBackendName;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// Folder with configs.yaml and layouts. Defaults are written there if it's empty
+    #[arg(long, value_name = "DIR", global = true)]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    // Without a subcommand the app runs as before
+    #[command(flatten)]
+    pub run: RunArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Read the controller and emit input. Default
+    Run(RunArgs),
+    /// Check that a layout loads and its zones can be built
+    Validate { layout: String },
+    /// List layouts from the layouts folder
+    ListLayouts,
+    /// Print angle ranges of every zone mapper of a layout
+    ShowZones { layout: String },
+    /// Print normalized events without emitting input
+    DumpEvents {
+        /// Input backend: Auto, Steamy or Gilrs. Overrides 'backend' from configs.yaml
+        #[arg(long)]
+        backend: Option<InputBackendName>,
+    },
+    /// Write JSON Schemas of layouts and configs.yaml for editors
+    Schema {
+        /// Defaults to 'schemas' in the configs folder
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+    },
+    /// Convert a Steam Input controller config (VDF) into a layout
+    ImportVdf {
+        file: PathBuf,
+        /// Name of the new layout. Defaults to the file name
+        #[arg(long)]
+        name: Option<String>,
+        /// Layout to take everything that is not imported from
+        #[arg(long, default_value = "desktop")]
+        base: String,
+    },
+    /// Convert an SC-Controller profile (.sccprofile) into a layout
+    ImportScc {
+        file: PathBuf,
+        /// Name of the new layout. Defaults to the file name
+        #[arg(long)]
+        name: Option<String>,
+        /// Layout to take everything that is not imported from
+        #[arg(long, default_value = "desktop")]
+        base: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    /// Buttons layout. Overrides 'buttons_layout' from configs.yaml
+    #[arg(long)]
+    pub layout: Option<String>,
+
+    /// Input backend: Auto, Steamy or Gilrs. Overrides 'backend' from configs.yaml
+    #[arg(long)]
+    pub backend: Option<InputBackendName>,
+
+    /// Record normalized events to a JSON Lines file in this directory
+    #[arg(long, value_name = "DIR", num_args = 0..=1, default_missing_value = "recordings")]
+    pub record: Option<PathBuf>,
+
+    /// Replay a recorded session instead of reading a controller
+    #[arg(long, value_name = "FILE", conflicts_with = "backend")]
+    pub replay: Option<PathBuf>,
+
+    /// Replay speed multiplier. 0 replays as fast as possible
+    #[arg(long, default_value_t = 1.0, requires = "replay")]
+    pub replay_speed: f32,
+}
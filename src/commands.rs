@@ -0,0 +1,134 @@
+use crate::cli::RunArgs;
+use crate::configs::{InputBackendName, LayoutConfigs, MainConfigs};
+use crate::file_ops::yaml_filepath;
+use crate::input_backend::{select_backend, InputBackend};
+use crate::layout_import::ImportedLayout;
+use crate::output_sink::RecordingSink;
+use crate::replay_specific::ReplayBackend;
+use crate::utils::TerminationStatus;
+use crate::writing_thread::EventWriter;
+use color_eyre::eyre::{bail, OptionExt, Result};
+use log::debug;
+use std::fs::{read_dir, read_to_string, write};
+use std::path::Path;
+
+fn run_backend(mut backend: Box<dyn InputBackend>, mut configs: MainConfigs) -> Result<()> {
+    let termination_status = TerminationStatus::default();
+
+    // Writing threads rely on the backend that is actually used
+    configs.backend = backend.name();
+    debug!("Backend: {}", configs.backend);
+
+    termination_status.check_result(backend.run(&configs, &termination_status));
+
+    Ok(())
+}
+
+pub fn run(run_args: RunArgs, mut configs: MainConfigs) -> Result<()> {
+    if let Some(layout_name) = run_args.layout {
+        configs = configs.with_layout(layout_name.as_str())?;
+    }
+    if let Some(backend_name) = run_args.backend {
+        configs.backend = backend_name;
+    }
+    if let Some(recording_dir) = run_args.record {
+        configs.recording_dir = Some(recording_dir);
+    }
+
+    let backend: Box<dyn InputBackend> = match run_args.replay {
+        None => select_backend(configs.backend)?,
+        Some(filepath) => Box::new(ReplayBackend::new(filepath, run_args.replay_speed)?),
+    };
+    run_backend(backend, configs)
+}
+
+fn load_layout(layout_name: &str, configs: &MainConfigs) -> Result<EventWriter<RecordingSink>> {
+    let layout_configs = LayoutConfigs::load(layout_name, configs.layouts_dir())?;
+    EventWriter::from_layout(&layout_configs, false, RecordingSink::new())
+}
+
+pub fn validate(layout_name: &str, configs: &MainConfigs) -> Result<()> {
+    load_layout(layout_name, configs)?;
+    println!("Layout '{}' is valid", layout_name);
+    Ok(())
+}
+
+pub fn list_layouts(configs: &MainConfigs) -> Result<()> {
+    let mut layout_names = vec![];
+    for entry in read_dir(configs.layouts_dir())? {
+        let filepath = entry?.path();
+        if filepath.extension().is_some_and(|ext| ext == "yaml") {
+            if let Some(layout_name) = filepath.file_stem().and_then(|name| name.to_str()) {
+                layout_names.push(layout_name.to_string());
+            }
+        }
+    }
+    layout_names.sort();
+
+    let active_layout_name = configs.layout_names_cfg.buttons_layout_name.as_str();
+    for layout_name in layout_names {
+        match layout_name == active_layout_name {
+            true => println!("* {}", layout_name),
+            false => println!("  {}", layout_name),
+        }
+    }
+    Ok(())
+}
+
+pub fn show_zones(layout_name: &str, configs: &MainConfigs) -> Result<()> {
+    let event_writer = load_layout(layout_name, configs)?;
+
+    for (mapper_name, zone_mapper) in event_writer.zone_mappers() {
+        println!("[{}]", mapper_name);
+        for (first_angle, last_angle, value) in zone_mapper.angle_table() {
+            println!("{:>3}-{:<3} {}", first_angle, last_angle, value);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+pub fn dump_events(backend_name: Option<InputBackendName>, mut configs: MainConfigs) -> Result<()> {
+    if let Some(backend_name) = backend_name {
+        configs.backend = backend_name;
+    }
+    configs.dump_events = true;
+
+    run_backend(select_backend(configs.backend)?, configs)
+}
+
+pub type Importer = fn(&str) -> Result<ImportedLayout>;
+
+pub fn import_layout(
+    importer: Importer,
+    filepath: &Path,
+    layout_name: Option<String>,
+    base_layout_name: &str,
+    configs: &MainConfigs,
+) -> Result<()> {
+    let layout_name = match layout_name {
+        Some(layout_name) => layout_name,
+        None => filepath
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .ok_or_eyre("Cannot get the layout name from the file name")?
+            .to_string(),
+    };
+    let layout_path = yaml_filepath(configs.layouts_dir(), layout_name.as_str());
+    if layout_path.exists() {
+        bail!("Layout '{}' already exists", layout_path.display())
+    }
+
+    let content = read_to_string(filepath)?;
+    let imported = importer(content.as_str())?;
+    write(&layout_path, imported.to_layout_yaml(base_layout_name)?)?;
+    println!("Layout is written to '{}'", layout_path.display());
+
+    if !imported.skipped.is_empty() {
+        println!("Not imported:");
+        for item in &imported.skipped {
+            println!("  {}", item);
+        }
+    }
+    validate(layout_name.as_str(), configs)
+}
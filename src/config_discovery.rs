@@ -0,0 +1,115 @@
+use crate::file_ops::{get_home_dir, get_project_dir};
+use color_eyre::eyre::Result;
+use log::debug;
+use std::env;
+use std::fs::{create_dir_all, write};
+use std::path::{Path, PathBuf};
+
+const PROJECT_NAME: &str = "JoystickFullRust";
+const APP_DIR_NAME: &str = "joystick-full-rust";
+const SYSTEM_CONFIGS_DIR: &str = "/etc/joystick-full-rust";
+const MAIN_CONFIGS_FILE: &str = "configs.yaml";
+
+// Written out on the first run
+const DEFAULT_CONFIGS: [(&str, &str); 12] = [
+    ("configs.yaml", include_str!("../config/configs.yaml")),
+    ("typing.yaml", include_str!("../config/typing.yaml")),
+    ("chords.yaml", include_str!("../config/chords.yaml")),
+    (
+        "devices/dualshock.yaml",
+        include_str!("../config/devices/dualshock.yaml"),
+    ),
+    (
+        "devices/steam.yaml",
+        include_str!("../config/devices/steam.yaml"),
+    ),
+    (
+        "devices/xbox.yaml",
+        include_str!("../config/devices/xbox.yaml"),
+    ),
+    (
+        "layouts/desktop.yaml",
+        include_str!("../config/layouts/desktop.yaml"),
+    ),
+    (
+        "layouts/raft.yaml",
+        include_str!("../config/layouts/raft.yaml"),
+    ),
+    (
+        "layouts/risk.yaml",
+        include_str!("../config/layouts/risk.yaml"),
+    ),
+    (
+        "layouts/satisfactory.yaml",
+        include_str!("../config/layouts/satisfactory.yaml"),
+    ),
+    (
+        "layouts/valheim.yaml",
+        include_str!("../config/layouts/valheim.yaml"),
+    ),
+    (
+        "layouts/wmd.yaml",
+        include_str!("../config/layouts/wmd.yaml"),
+    ),
+];
+
+fn has_main_configs(configs_dir: &Path) -> bool {
+    configs_dir.join(MAIN_CONFIGS_FILE).is_file()
+}
+
+pub fn user_configs_dir() -> Result<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config_home) if !config_home.is_empty() => PathBuf::from(config_home),
+        _ => get_home_dir()?.join(".config"),
+    };
+    Ok(config_home.join(APP_DIR_NAME))
+}
+
+// Inside the source checkout
+fn legacy_configs_dir() -> Option<PathBuf> {
+    get_project_dir(PROJECT_NAME)
+        .ok()
+        .map(|project_dir| project_dir.join("config"))
+}
+
+// Existing files are kept
+pub fn write_default_configs(configs_dir: &Path) -> Result<()> {
+    for (filename, content) in DEFAULT_CONFIGS {
+        let filepath = configs_dir.join(filename);
+        if filepath.exists() {
+            continue;
+        }
+        if let Some(parent) = filepath.parent() {
+            create_dir_all(parent)?;
+        }
+        write(filepath, content)?;
+    }
+    Ok(())
+}
+
+/// Order: explicit path, $XDG_CONFIG_HOME, system folder, source checkout.
+/// Defaults are written to the explicit or the user folder if nothing is found
+pub fn find_configs_dir(explicit_dir: Option<&Path>) -> Result<PathBuf> {
+    if let Some(explicit_dir) = explicit_dir {
+        if !has_main_configs(explicit_dir) {
+            println!("Writing default configs to '{}'", explicit_dir.display());
+            write_default_configs(explicit_dir)?;
+        }
+        return Ok(explicit_dir.to_path_buf());
+    }
+
+    let user_dir = user_configs_dir()?;
+    let mut candidates = vec![user_dir.clone(), PathBuf::from(SYSTEM_CONFIGS_DIR)];
+    candidates.extend(legacy_configs_dir());
+
+    for configs_dir in candidates {
+        if has_main_configs(configs_dir.as_path()) {
+            debug!("Configs: {}", configs_dir.display());
+            return Ok(configs_dir);
+        }
+    }
+
+    println!("Writing default configs to '{}'", user_dir.display());
+    write_default_configs(user_dir.as_path())?;
+    Ok(user_dir)
+}
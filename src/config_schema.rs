@@ -0,0 +1,97 @@
+use crate::configs::{
+    ButtonActionsRaw, ButtonBindingRaw, ButtonsLayoutRaw, ChordsConfigs, LayoutConfigs,
+    MainConfigs, TypingConfigs,
+};
+use crate::key_codes::{key_code_names, named_code_pattern};
+use color_eyre::eyre::{OptionExt, Result};
+use schemars::gen::SchemaGenerator;
+use schemars::schema::Schema;
+use schemars::{schema_for, JsonSchema};
+use serde_json::{json, Map, Value};
+use std::fs::{create_dir_all, write};
+use std::path::Path;
+
+pub const LAYOUT_SCHEMA_FILENAME: &str = "layout.schema.json";
+pub const CONFIGS_SCHEMA_FILENAME: &str = "configs.schema.json";
+pub const TYPING_SCHEMA_FILENAME: &str = "typing.schema.json";
+pub const CHORDS_SCHEMA_FILENAME: &str = "chords.schema.json";
+
+fn to_schema(value: Value) -> Schema {
+    serde_json::from_value(value).expect("Schema is built from a valid JSON")
+}
+
+// Codes of a single button, including 'LAYOUT(name)' and 'LAYER(name)'
+pub fn key_codes_schema(_gen: &mut SchemaGenerator) -> Schema {
+    to_schema(json!({
+        "type": "array",
+        "items": {
+            "anyOf": [
+                { "type": "string", "enum": key_code_names() },
+                { "type": "string", "pattern": named_code_pattern() },
+            ]
+        }
+    }))
+}
+
+impl JsonSchema for ButtonBindingRaw {
+    fn schema_name() -> String {
+        "ButtonBinding".to_string()
+    }
+
+    // List of codes or codes per action
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let key_codes = serde_json::to_value(key_codes_schema(gen)).unwrap_or_default();
+        let actions =
+            serde_json::to_value(gen.subschema_for::<ButtonActionsRaw>()).unwrap_or_default();
+        to_schema(json!({ "anyOf": [key_codes, actions] }))
+    }
+}
+
+impl JsonSchema for ButtonsLayoutRaw {
+    fn schema_name() -> String {
+        "ButtonsLayout".to_string()
+    }
+
+    // Button names are taken from the struct itself, so they can't get out of sync
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let button_names = serde_json::to_value(ButtonsLayoutRaw::default())
+            .ok()
+            .and_then(|value| value.as_object().cloned())
+            .unwrap_or_default();
+
+        let binding = serde_json::to_value(ButtonBindingRaw::json_schema(gen)).unwrap_or_default();
+        let mut properties = Map::new();
+        for button_name in button_names.keys() {
+            properties.insert(button_name.clone(), binding.clone());
+        }
+        to_schema(json!({
+            "type": "object",
+            "properties": properties,
+            "additionalProperties": false,
+        }))
+    }
+}
+
+pub fn write_schemas(output_dir: &Path) -> Result<()> {
+    create_dir_all(output_dir)?;
+    for (filename, schema) in [
+        (LAYOUT_SCHEMA_FILENAME, schema_for!(LayoutConfigs)),
+        (CONFIGS_SCHEMA_FILENAME, schema_for!(MainConfigs)),
+        (TYPING_SCHEMA_FILENAME, schema_for!(TypingConfigs)),
+        (CHORDS_SCHEMA_FILENAME, schema_for!(ChordsConfigs)),
+    ] {
+        let filepath = output_dir.join(filename);
+        write(&filepath, serde_json::to_string_pretty(&schema)?)?;
+        println!("Schema is written to '{}'", filepath.display());
+    }
+
+    let layout_schema = output_dir.join(LAYOUT_SCHEMA_FILENAME);
+    let layout_schema = layout_schema
+        .to_str()
+        .ok_or_eyre("Schema path is not valid UTF-8")?;
+    println!(
+        "Add this line at the top of a layout to use it in editors:\n# yaml-language-server: $schema={}",
+        layout_schema
+    );
+    Ok(())
+}
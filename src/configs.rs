@@ -1,25 +1,37 @@
-use crate::file_ops::{get_project_dir, read_yaml};
-use crate::key_codes::key_code_from_config;
+use crate::chords::{chord_of, Chord};
+use crate::config_schema::key_codes_schema;
+use crate::file_ops::{read_yaml, yaml_filepath};
+use crate::key_codes::{
+    key_code_from_config, key_codes_from_config, key_strokes_from_text, locate_key_code_error,
+    LayoutSwitch,
+};
+use crate::layout_inheritance::resolve_layout;
 use crate::match_event::ButtonName;
-use crate::math_ops::{coord_to_f32, Angle};
+use crate::math_ops::{coord_to_f32, Angle, ZoneAllowedRange};
 use crate::steamy_state::SteamyInputCoord;
 use ahash::AHashMap;
-use color_eyre::eyre::{bail, OptionExt, Result};
-use serde::{Deserialize, Serialize};
+use color_eyre::eyre::{bail, OptionExt, Result, WrapErr};
+use schemars::JsonSchema;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
+use strum_macros::{AsRefStr, Display, EnumString};
 use universal_input::{KeyCode, KeyCodes};
 
-const PROJECT_NAME: &str = "JoystickFullRust";
-
-#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct JitterThresholdConfigs {
     pub left_pad: f32,
     pub right_pad: f32,
     pub stick: f32,
 }
 
-#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct FingerRotationConfigs {
     pub use_rotation: bool,
     pub left_pad: i16,
@@ -27,14 +39,17 @@ pub struct FingerRotationConfigs {
     pub stick: i16,
 }
 
-#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct AxisCorrection {
     #[serde(alias = "x")]
+    #[schemars(rename = "x")]
     pub _x: i16,
     #[serde(skip)]
     pub x: f32,
 
     #[serde(alias = "y")]
+    #[schemars(rename = "y")]
     pub _y: i16,
     #[serde(skip)]
     pub y: f32,
@@ -54,7 +69,8 @@ impl AxisCorrection {
     }
 }
 
-#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct AxisCorrectionConfigs {
     pub use_correction: bool,
     pub left_pad: AxisCorrection,
@@ -75,33 +91,264 @@ impl AxisCorrectionConfigs {
     }
 }
 
-#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ScrollConfigs {
     pub speed: u16,
     pub horizontal_threshold: f32,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(
+    EnumString,
+    AsRefStr,
+    Display,
+    Default,
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+pub enum GyroActivation {
+    #[default]
+    AlwaysOn,
+    // Gyro moves the cursor only while the button is held
+    Hold,
+    // Gyro is paused while the button is held, like lifting a mouse to reposition it
+    Ratchet,
+}
+
+#[derive(
+    EnumString,
+    AsRefStr,
+    Display,
+    Default,
+    Eq,
+    PartialEq,
+    Copy,
+    Clone,
+    Debug,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum InputBackendName {
+    // Steam Controller if it's connected. Gilrs otherwise
+    #[default]
+    Auto,
+    Steamy,
+    Gilrs,
+    // Replays a recorded session. Selected with '--replay'
+    Replay,
+}
+
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GyroConfigs {
+    pub activation: GyroActivation,
+    #[serde(default)]
+    pub enable_btn: ButtonName,
+    pub x_sensitivity: u16,
+    pub y_sensitivity: u16,
+    #[serde(default)]
+    pub invert_x: bool,
+    #[serde(default)]
+    pub invert_y: bool,
+    pub jitter_threshold: f32,
+    #[serde(skip)]
+    pub use_gyro: bool,
+}
+
+impl GyroConfigs {
+    pub fn load(&mut self) -> Result<()> {
+        match self.activation {
+            GyroActivation::AlwaysOn => {}
+            GyroActivation::Hold | GyroActivation::Ratchet => {
+                if self.enable_btn == ButtonName::None {
+                    bail!(
+                        "[Gyro] 'enable_btn' has to be specified for '{}' activation",
+                        self.activation
+                    )
+                }
+            }
+        }
+        self.use_gyro = true;
+        Ok(())
+    }
+
+    pub fn load_and_return(&self) -> Result<Self> {
+        let mut res = *self;
+        res.load()?;
+        Ok(res)
+    }
+
+    pub fn is_active_on_start(&self) -> bool {
+        self.activation != GyroActivation::Hold
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TiltConfigs {
+    pub zones: ZoneMappingConfigs,
+    // Resting pose in orientation units. X: roll, Y: pitch
+    #[serde(default)]
+    pub neutral: AxisCorrection,
+    #[serde(default)]
+    pub recenter_btn: ButtonName,
+    // Tilt in degrees that corresponds to the full stick deflection
+    pub max_angle: u8,
+    #[serde(skip)]
+    pub max_value: f32,
+    #[serde(alias = "deadzone_pct")]
+    #[schemars(rename = "deadzone_pct")]
+    _deadzone_pct: u8,
+    #[serde(skip)]
+    pub deadzone: f32,
+    //
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub right: Vec<String>,
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub up: Vec<String>,
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub left: Vec<String>,
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub down: Vec<String>,
+    // Same order as stick zones: right, up, left, down
+    #[serde(skip)]
+    pub zones_keys: Vec<KeyCodes>,
+    //
+    #[serde(skip)]
+    pub use_tilt: bool,
+}
+
+impl TiltConfigs {
+    pub fn load(&mut self) -> Result<()> {
+        self.zones.load()?;
+        self.neutral.load();
+
+        if !(self.max_angle > 0 && self.max_angle <= 180) {
+            bail!("Incorrect value for 'max_angle': '{}'", self.max_angle);
+        }
+        // Orientation range [-1.0, 1.0] covers a full turn
+        self.max_value = self.max_angle as f32 / 180.0;
+        self.deadzone = convert_pct(self._deadzone_pct);
+
+        self.zones_keys = vec![
+            key_codes_from_config("Tilt.right", &self.right)?,
+            key_codes_from_config("Tilt.up", &self.up)?,
+            key_codes_from_config("Tilt.left", &self.left)?,
+            key_codes_from_config("Tilt.down", &self.down)?,
+        ];
+
+        self.use_tilt = true;
+        Ok(())
+    }
+
+    pub fn load_and_return(&self) -> Result<Self> {
+        let mut res = self.clone();
+        res.load()?;
+        Ok(res)
+    }
+}
+
+// Thresholds are in accelerometer units where 1.0 is the full sensor range
+#[derive(Clone, Debug, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GesturesConfigs {
+    pub flick_threshold: f32,
+    #[serde(alias = "flick_cooldown_ms")]
+    #[schemars(rename = "flick_cooldown_ms")]
+    _flick_cooldown_ms: u16,
+    #[serde(skip)]
+    pub flick_cooldown: Duration,
+
+    pub shake_threshold: f32,
+    // Amount of direction changes to count as a shake
+    pub shake_reversals: u8,
+    // Flicks fire once it passes, as they can be the start of a shake
+    #[serde(alias = "shake_window_ms")]
+    #[schemars(rename = "shake_window_ms")]
+    _shake_window_ms: u16,
+    #[serde(skip)]
+    pub shake_window: Duration,
+
+    // Angle in degrees between gravity and the resting pose
+    pub tilt_angle: u8,
+    #[serde(skip)]
+    pub tilt_threshold: f32,
+    #[serde(alias = "tilt_hold_ms")]
+    #[schemars(rename = "tilt_hold_ms")]
+    _tilt_hold_ms: u16,
+    #[serde(skip)]
+    pub tilt_hold: Duration,
+
+    #[serde(skip)]
+    pub use_gestures: bool,
+}
+
+impl GesturesConfigs {
+    pub fn load(&mut self) -> Result<()> {
+        if self.shake_reversals == 0 {
+            bail!(
+                "Incorrect value for 'shake_reversals': '{}'",
+                self.shake_reversals
+            );
+        }
+        if !(self.tilt_angle > 0 && self.tilt_angle < 90) {
+            bail!("Incorrect value for 'tilt_angle': '{}'", self.tilt_angle);
+        }
+
+        self.flick_cooldown = Duration::from_millis(self._flick_cooldown_ms as u64);
+        self.shake_window = Duration::from_millis(self._shake_window_ms as u64);
+        self.tilt_hold = Duration::from_millis(self._tilt_hold_ms as u64);
+        self.tilt_threshold = (self.tilt_angle as f32).to_radians().sin();
+
+        self.use_gestures = true;
+        Ok(())
+    }
+
+    pub fn load_and_return(&self) -> Result<Self> {
+        let mut res = *self;
+        res.load()?;
+        Ok(res)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct MainGeneralConfigs {
     pub commands_channel_size: i32,
     pub steamy_channel_size: i32,
 
     #[serde(alias = "steamy_read_interrupt_interval")]
+    #[schemars(rename = "steamy_read_interrupt_interval")]
     pub _steamy_read_interrupt_interval: u16,
     #[serde(skip)]
     pub steamy_read_interrupt_interval: Duration,
 
     #[serde(alias = "input_raw_refresh_interval")]
+    #[schemars(rename = "input_raw_refresh_interval")]
     pub _input_raw_refresh_interval: u16,
     #[serde(skip)]
     pub input_raw_refresh_interval: Duration,
 
     #[serde(alias = "input_buffer_refresh_interval")]
+    #[schemars(rename = "input_buffer_refresh_interval")]
     pub _input_buffer_refresh_interval: u16,
     #[serde(skip)]
     pub input_buffer_refresh_interval: Duration,
 
     #[serde(alias = "mouse_refresh_interval")]
+    #[schemars(rename = "mouse_refresh_interval")]
     pub _mouse_refresh_interval: u16,
     #[serde(skip)]
     pub mouse_refresh_interval: Duration,
@@ -119,24 +366,223 @@ impl MainGeneralConfigs {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MotionSensorConfigs {
+    // Calibrate even if the bias for the controller is already stored
+    pub calibrate_on_start: bool,
+    #[serde(alias = "calibration_ms")]
+    #[schemars(rename = "calibration_ms")]
+    _calibration_ms: u16,
+    #[serde(skip)]
+    pub calibration_duration: Duration,
+    // Gives up if the controller doesn't rest long enough
+    #[serde(alias = "calibration_timeout_ms")]
+    #[schemars(rename = "calibration_timeout_ms")]
+    _calibration_timeout_ms: u16,
+    #[serde(skip)]
+    pub calibration_timeout: Duration,
+
+    // Max change of acceleration between samples to count the controller as resting
+    pub still_threshold: f32,
+    #[serde(alias = "still_ms")]
+    #[schemars(rename = "still_ms")]
+    _still_ms: u16,
+    #[serde(skip)]
+    pub still_duration: Duration,
+
+    // Keep adjusting the bias whenever the controller rests
+    pub drift_correction: bool,
+}
+
+// Same as in configs.yaml. Older configs don't have the section
+impl Default for MotionSensorConfigs {
+    fn default() -> Self {
+        Self {
+            calibrate_on_start: false,
+            _calibration_ms: 2000,
+            calibration_duration: Duration::ZERO,
+            _calibration_timeout_ms: 15000,
+            calibration_timeout: Duration::ZERO,
+            still_threshold: 0.002,
+            _still_ms: 1000,
+            still_duration: Duration::ZERO,
+            drift_correction: true,
+        }
+    }
+}
+
+impl MotionSensorConfigs {
+    pub fn load(&mut self) -> Result<()> {
+        if self._calibration_ms == 0 {
+            bail!(
+                "Incorrect value for 'calibration_ms': '{}'",
+                self._calibration_ms
+            );
+        }
+
+        if self._calibration_timeout_ms < self._calibration_ms {
+            bail!(
+                "'calibration_timeout_ms' ({}) is less than 'calibration_ms' ({})",
+                self._calibration_timeout_ms,
+                self._calibration_ms
+            );
+        }
+
+        self.calibration_duration = Duration::from_millis(self._calibration_ms as u64);
+        self.calibration_timeout = Duration::from_millis(self._calibration_timeout_ms as u64);
+        self.still_duration = Duration::from_millis(self._still_ms as u64);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppRuleConfigs {
+    // Process name or absolute path of the executable
+    pub process: String,
+    #[serde(alias = "buttons_layout")]
+    #[schemars(rename = "buttons_layout")]
+    pub buttons_layout_name: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AppRulesConfigs {
+    // Can point to a fake tree
+    pub proc_root: PathBuf,
+    #[serde(alias = "check_interval_ms")]
+    #[schemars(rename = "check_interval_ms")]
+    _check_interval_ms: u16,
+    #[serde(skip)]
+    pub check_interval: Duration,
+    // First matching rule wins
+    #[serde(default)]
+    pub rules: Vec<AppRuleConfigs>,
+}
+
+// Same as in configs.yaml. Older configs don't have the section
+impl Default for AppRulesConfigs {
+    fn default() -> Self {
+        Self {
+            proc_root: PathBuf::from("/proc"),
+            _check_interval_ms: 1000,
+            check_interval: Duration::ZERO,
+            rules: vec![],
+        }
+    }
+}
+
+impl AppRulesConfigs {
+    pub fn load(&mut self) -> Result<()> {
+        if self._check_interval_ms == 0 {
+            bail!(
+                "Incorrect value for 'check_interval_ms': '{}'",
+                self._check_interval_ms
+            );
+        }
+
+        self.check_interval = Duration::from_millis(self._check_interval_ms as u64);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct GamepadLayoutConfigs {
+    // Gamepad name or UUID
+    pub gamepad: String,
+    #[serde(alias = "buttons_layout", default)]
+    #[schemars(rename = "buttons_layout")]
+    pub buttons_layout_name: Option<String>,
+    // Picked by the gamepad name if not set
+    #[serde(alias = "device_profile", default)]
+    #[schemars(rename = "device_profile")]
+    pub device_profile_name: Option<String>,
+}
+
+impl GamepadLayoutConfigs {
+    fn matches(&self, name: &str, uuid: &str) -> bool {
+        self.gamepad.eq_ignore_ascii_case(uuid) || self.gamepad == name
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct LayoutNamesConfigs {
     #[serde(alias = "typing_layout")]
+    #[schemars(rename = "typing_layout")]
     pub typing_layout_name: String,
     #[serde(alias = "buttons_layout")]
+    #[schemars(rename = "buttons_layout")]
     pub buttons_layout_name: String,
+    #[serde(default)]
+    pub gamepads: Vec<GamepadLayoutConfigs>,
+    // Order for NEXT_LAYOUT and PREV_LAYOUT
+    #[serde(default)]
+    pub playlist: Vec<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+impl LayoutNamesConfigs {
+    // Gamepads with their own layout don't follow app rules
+    pub fn explicit_gamepad_layout_name(&self, name: &str, uuid: &str) -> Option<&str> {
+        for gamepad_layout in &self.gamepads {
+            if let Some(layout_name) = &gamepad_layout.buttons_layout_name {
+                if gamepad_layout.matches(name, uuid) {
+                    return Some(layout_name.as_str());
+                }
+            }
+        }
+        None
+    }
+
+    pub fn gamepad_layout_name(&self, name: &str, uuid: &str) -> &str {
+        self.explicit_gamepad_layout_name(name, uuid)
+            .unwrap_or(self.buttons_layout_name.as_str())
+    }
+
+    pub fn gamepad_profile_name(&self, name: &str, uuid: &str) -> Option<&str> {
+        for gamepad_layout in &self.gamepads {
+            if let Some(profile_name) = &gamepad_layout.device_profile_name {
+                if gamepad_layout.matches(name, uuid) {
+                    return Some(profile_name.as_str());
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct MainConfigs {
     pub is_left_pad_bytes_dump: bool,
+    #[serde(default)]
+    pub backend: InputBackendName,
     #[serde(alias = "Layouts")]
+    #[schemars(rename = "Layouts")]
     pub layout_names_cfg: LayoutNamesConfigs,
     #[serde(alias = "General")]
+    #[schemars(rename = "General")]
     pub general: MainGeneralConfigs,
+    #[serde(alias = "MotionSensor", default)]
+    #[schemars(rename = "MotionSensor")]
+    pub motion_sensor_cfg: MotionSensorConfigs,
+    #[serde(alias = "AppRules", default)]
+    #[schemars(rename = "AppRules")]
+    pub app_rules_cfg: AppRulesConfigs,
 
     #[serde(skip)]
     pub layout_configs: LayoutConfigs,
+    #[serde(skip)]
+    pub typing_cfg: TypingConfigs,
+    #[serde(skip)]
+    pub configs_dir: PathBuf,
+    // Set from the command line
+    #[serde(skip)]
+    pub recording_dir: Option<PathBuf>,
+    #[serde(skip)]
+    pub dump_events: bool,
 }
 
 pub fn convert_pct(value: u8) -> f32 {
@@ -144,34 +590,66 @@ pub fn convert_pct(value: u8) -> f32 {
 }
 
 impl MainConfigs {
-    pub fn load() -> Result<Self> {
-        let configs_dir = get_project_dir(PROJECT_NAME)?.join("config");
-        let layouts_dir = configs_dir.join("layouts");
-
+    /// Only configs.yaml. Active layout and typing configs are left empty
+    pub fn load_main(configs_dir: PathBuf) -> Result<Self> {
         let mut main_configs: Self = read_yaml(configs_dir.as_path(), "configs")?;
 
         main_configs.general.load();
+        main_configs.motion_sensor_cfg.load()?;
+        main_configs.app_rules_cfg.load()?;
+        main_configs.configs_dir = configs_dir;
+
+        Ok(main_configs)
+    }
+
+    pub fn load(configs_dir: PathBuf) -> Result<Self> {
+        let mut main_configs = Self::load_main(configs_dir)?;
 
         main_configs.layout_configs = LayoutConfigs::load(
             main_configs.layout_names_cfg.buttons_layout_name.as_str(),
-            layouts_dir.as_path(),
+            main_configs.layouts_dir(),
         )?;
+        main_configs.typing_cfg = TypingConfigs::load(
+            main_configs.configs_dir.as_path(),
+            main_configs.layout_names_cfg.typing_layout_name.as_str(),
+        )
+        .wrap_err("Typing layout")?;
 
         Ok(main_configs)
     }
+
+    pub fn layouts_dir(&self) -> PathBuf {
+        self.configs_dir.join("layouts")
+    }
+
+    pub fn devices_dir(&self) -> PathBuf {
+        self.configs_dir.join("devices")
+    }
+
+    pub fn with_layout(&self, layout_name: &str) -> Result<Self> {
+        let mut configs = self.clone();
+        if configs.layout_names_cfg.buttons_layout_name != layout_name {
+            configs.layout_names_cfg.buttons_layout_name = layout_name.to_string();
+            configs.layout_configs = LayoutConfigs::load(layout_name, configs.layouts_dir())?;
+        }
+        Ok(configs)
+    }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ZoneMappingConfigs {
     pub diagonal_zones: bool,
     pub zone_range: Angle,
 
     #[serde(alias = "start_threshold_pct")]
+    #[schemars(rename = "start_threshold_pct")]
     _start_threshold_pct: u8,
     #[serde(skip)]
     pub start_threshold: f32,
 
     #[serde(alias = "shift_threshold_pct")]
+    #[schemars(rename = "shift_threshold_pct")]
     _shift_threshold_pct: Option<u8>,
     #[serde(skip)]
     pub shift_threshold: f32,
@@ -181,6 +659,8 @@ pub struct ZoneMappingConfigs {
 
 impl ZoneMappingConfigs {
     pub fn load(&mut self) -> Result<()> {
+        // Same check as for zone mappers. Fails before the controller loop starts
+        ZoneAllowedRange::from_one_value(self.zone_range, self.diagonal_zones)?;
         self.start_threshold = convert_pct(self._start_threshold_pct);
 
         (self.shift_threshold, self.use_shift) = match self._shift_threshold_pct {
@@ -203,92 +683,203 @@ impl ZoneMappingConfigs {
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct LayoutGeneralConfigs {
     pub gaming_mode: bool,
     pub repeat_keys: bool,
     pub steamy_use_left_pad: bool,
     #[serde(alias = "triggers_threshold_pct")]
+    #[schemars(rename = "triggers_threshold_pct")]
     _triggers_threshold_pct: u8,
     #[serde(skip)]
     pub triggers_threshold: f32,
     pub mouse_speed: u16,
+    // Default times of button actions. Each binding can override them
+    #[serde(alias = "hold_ms", default)]
+    #[schemars(rename = "hold_ms")]
+    _hold_ms: Option<u16>,
+    #[serde(alias = "long_press_ms", default)]
+    #[schemars(rename = "long_press_ms")]
+    _long_press_ms: Option<u16>,
+    #[serde(alias = "double_tap_ms", default)]
+    #[schemars(rename = "double_tap_ms")]
+    _double_tap_ms: Option<u16>,
+    #[serde(skip)]
+    pub action_timeouts: ActionTimeouts,
+}
+
+const DEFAULT_HOLD_MS: u16 = 200;
+const DEFAULT_LONG_PRESS_MS: u16 = 500;
+const DEFAULT_DOUBLE_TAP_MS: u16 = 250;
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct ActionTimeouts {
+    pub hold: Duration,
+    pub long_press: Duration,
+    // Between the release and the second press
+    pub double_tap: Duration,
 }
 
 impl LayoutGeneralConfigs {
     pub fn load(&mut self) -> Result<()> {
         self.triggers_threshold = convert_pct(self._triggers_threshold_pct);
+        let to_duration = |value: Option<u16>, default: u16| {
+            Duration::from_millis(value.unwrap_or(default) as u64)
+        };
+        self.action_timeouts = ActionTimeouts {
+            hold: to_duration(self._hold_ms, DEFAULT_HOLD_MS),
+            long_press: to_duration(self._long_press_ms, DEFAULT_LONG_PRESS_MS),
+            double_tap: to_duration(self._double_tap_ms, DEFAULT_DOUBLE_TAP_MS),
+        };
         Ok(())
     }
 }
 
-#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct LayoutConfigs {
+    // Base layout. Only sections and buttons that differ have to be specified
+    #[serde(default)]
+    pub extends: Option<String>,
+    // Partial layouts merged on top of the base one, e.g. shared calibration
+    #[serde(default)]
+    pub include: Vec<String>,
+
     #[serde(alias = "ButtonsLayout")]
+    #[schemars(rename = "ButtonsLayout")]
     _buttons_layout_raw: ButtonsLayoutRaw,
     #[serde(skip)]
     pub buttons_layout: ButtonsLayout,
 
+    // Active while a button with 'LAYER(name)' is held
+    #[serde(alias = "Layers", default)]
+    #[schemars(rename = "Layers")]
+    _layers: BTreeMap<String, ButtonsLayoutRaw>,
+
     #[serde(alias = "General")]
+    #[schemars(rename = "General")]
     pub general: LayoutGeneralConfigs,
 
     #[serde(alias = "FingerRotation")]
+    #[schemars(rename = "FingerRotation")]
     pub _finger_rotation_cfg: Option<FingerRotationConfigs>,
     #[serde(skip)]
     pub finger_rotation_cfg: FingerRotationConfigs,
 
     #[serde(alias = "AxisCorrection")]
+    #[schemars(rename = "AxisCorrection")]
     pub axis_correction_cfg: AxisCorrectionConfigs,
 
     #[serde(alias = "Stick")]
+    #[schemars(rename = "Stick")]
     pub stick_zones_cfg: ZoneMappingConfigs,
 
     #[serde(alias = "WASD")]
+    #[schemars(rename = "WASD")]
     _wasd: Option<ZoneMappingConfigs>,
     #[serde(skip)]
     pub wasd_zones_cfg: ZoneMappingConfigs,
 
     #[serde(alias = "Scroll")]
+    #[schemars(rename = "Scroll")]
     _scroll: Option<ScrollConfigs>,
     #[serde(skip)]
     pub scroll_cfg: ScrollConfigs,
 
     #[serde(alias = "JitterThreshold")]
+    #[schemars(rename = "JitterThreshold")]
     pub jitter_threshold_cfg: JitterThresholdConfigs,
+
+    #[serde(alias = "Gyro")]
+    #[schemars(rename = "Gyro")]
+    _gyro: Option<GyroConfigs>,
+    #[serde(skip)]
+    pub gyro_cfg: GyroConfigs,
+
+    #[serde(alias = "Tilt")]
+    #[schemars(rename = "Tilt")]
+    _tilt: Option<TiltConfigs>,
+    #[serde(skip)]
+    pub tilt_cfg: TiltConfigs,
+
+    #[serde(alias = "Gestures")]
+    #[schemars(rename = "Gestures")]
+    _gestures: Option<GesturesConfigs>,
+    #[serde(skip)]
+    pub gestures_cfg: GesturesConfigs,
 }
 
 impl LayoutConfigs {
     pub fn load<S: AsRef<str>, P: AsRef<Path>>(layout_name: S, layout_dir: P) -> Result<Self> {
-        println!("Loading layout: '{}'", layout_name.as_ref());
-        let mut layout_configs: Self = read_yaml(layout_dir.as_ref(), layout_name)?;
+        let (layout_name, layout_dir) = (layout_name.as_ref(), layout_dir.as_ref());
+        println!("Loading layout: '{}'", layout_name);
 
-        layout_configs.general.load()?;
-        let gaming_mode = layout_configs.general.gaming_mode;
+        // Standalone layouts are parsed once, straight from the file to keep line numbers
+        let standalone_err = match read_yaml::<Self, _, _>(layout_dir, layout_name) {
+            Ok(layout_configs)
+                if layout_configs.extends.is_none() && layout_configs.include.is_empty() =>
+            {
+                let filepaths = [yaml_filepath(layout_dir, layout_name)];
+                return layout_configs
+                    .load_sections()
+                    .map_err(|err| locate_key_code_error(err, &filepaths));
+            }
+            Ok(_) => None,
+            // Layouts that extend others don't have to contain every section
+            Err(err) => Some(err),
+        };
+
+        let resolved = resolve_layout(layout_dir, layout_name)?;
+        if let (Some(err), true) = (standalone_err, resolved.is_single_file()) {
+            return Err(err);
+        }
+        let layout_configs: Self = serde_yml::from_value(resolved.value).wrap_err(format!(
+            "Layout '{}' merged from {:?}",
+            layout_name, resolved.filepaths
+        ))?;
+
+        layout_configs
+            .load_sections()
+            .map_err(|err| locate_key_code_error(err, &resolved.filepaths))
+    }
+
+    fn load_sections(mut self) -> Result<Self> {
+        self.general.load().wrap_err("[General]")?;
+        let gaming_mode = self.general.gaming_mode;
 
         match gaming_mode {
-            true => match layout_configs._wasd {
+            true => match self._wasd {
                 None => {
                     bail!("[WASD] has to be specified in gaming mode")
                 }
                 Some(ref wasd) => {
-                    layout_configs.wasd_zones_cfg = wasd.load_and_return()?;
+                    self.wasd_zones_cfg = wasd.load_and_return().wrap_err("[WASD]")?;
                 }
             },
-            false => match layout_configs._scroll {
+            false => match self._scroll {
                 None => {
                     bail!("[Scroll] has to be specified in desktop mode")
                 }
                 Some(scroll) => {
-                    layout_configs.scroll_cfg = scroll;
+                    self.scroll_cfg = scroll;
                 }
             },
         }
 
-        layout_configs.stick_zones_cfg.load()?;
-        layout_configs.axis_correction_cfg.load();
-        layout_configs.finger_rotation_cfg =
-            layout_configs
-                ._finger_rotation_cfg
+        self.stick_zones_cfg.load().wrap_err("[Stick]")?;
+        self.axis_correction_cfg.load();
+        if let Some(gyro) = self._gyro {
+            self.gyro_cfg = gyro.load_and_return().wrap_err("[Gyro]")?;
+        }
+        if let Some(ref tilt) = self._tilt {
+            self.tilt_cfg = tilt.load_and_return().wrap_err("[Tilt]")?;
+        }
+        if let Some(gestures) = self._gestures {
+            self.gestures_cfg = gestures.load_and_return().wrap_err("[Gestures]")?;
+        }
+        self.finger_rotation_cfg =
+            self._finger_rotation_cfg
                 .unwrap_or_else(|| FingerRotationConfigs {
                     use_rotation: false,
                     left_pad: 0,
@@ -296,49 +887,359 @@ impl LayoutConfigs {
                     stick: 0,
                 });
 
-        layout_configs.buttons_layout = ButtonsLayout::load(
-            layout_configs._buttons_layout_raw.clone(),
-            layout_configs.general.gaming_mode,
-        )?;
+        self.buttons_layout =
+            ButtonsLayout::load(self._buttons_layout_raw.clone(), self.general.gaming_mode)
+                .wrap_err("[ButtonsLayout]")?;
+        self.buttons_layout
+            .load_layers(&self._layers)
+            .wrap_err("[Layers]")?;
+
+        Ok(self)
+    }
+
+    pub fn uses_orientation(&self) -> bool {
+        self.gyro_cfg.use_gyro || self.tilt_cfg.use_tilt
+    }
+}
+
+// Zones of a ring cover the whole pad, 45 degrees each
+pub const TYPING_RING_ZONES: usize = 8;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TypingPadConfigs {
+    // Counter-clockwise starting from the right. Empty string leaves the zone empty
+    #[schemars(schema_with = "key_codes_schema")]
+    pub inner: Vec<String>,
+    // Optional ring closer to the edge of the pad
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub outer: Vec<String>,
+    // Type the key under the thumb
+    pub commit_btns: Vec<ButtonName>,
+    #[serde(skip)]
+    pub inner_keys: Vec<KeyCodes>,
+    #[serde(skip)]
+    pub outer_keys: Vec<KeyCodes>,
+}
 
-        Ok(layout_configs)
+fn typing_ring_keys(context: &str, codes: &[String]) -> Result<Vec<KeyCodes>> {
+    if codes.len() != TYPING_RING_ZONES {
+        bail!(
+            "'{}' has to contain {} zones. Provided: {}",
+            context,
+            TYPING_RING_ZONES,
+            codes.len()
+        )
+    }
+    codes
+        .iter()
+        .map(|code| key_codes_from_config(context, &[code]))
+        .collect()
+}
+
+impl TypingPadConfigs {
+    pub fn load(&mut self, pad_name: &str) -> Result<()> {
+        self.inner_keys = typing_ring_keys(format!("{pad_name}.inner").as_str(), &self.inner)?;
+        if !self.outer.is_empty() {
+            self.outer_keys = typing_ring_keys(format!("{pad_name}.outer").as_str(), &self.outer)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TypingConfigs {
+    #[serde(alias = "LeftPad")]
+    #[schemars(rename = "LeftPad")]
+    pub left_pad: TypingPadConfigs,
+    #[serde(alias = "RightPad")]
+    #[schemars(rename = "RightPad")]
+    pub right_pad: TypingPadConfigs,
+
+    // Nothing is typed closer to the center
+    #[serde(alias = "start_threshold_pct")]
+    #[schemars(rename = "start_threshold_pct")]
+    _start_threshold_pct: u8,
+    #[serde(skip)]
+    pub start_threshold: f32,
+
+    #[serde(alias = "outer_threshold_pct")]
+    #[schemars(rename = "outer_threshold_pct")]
+    _outer_threshold_pct: u8,
+    #[serde(skip)]
+    pub outer_threshold: f32,
+
+    // Used instead of the buttons layout while typing. Unset buttons keep their keys
+    #[serde(alias = "ButtonsLayout", default)]
+    #[schemars(rename = "ButtonsLayout")]
+    _buttons_layout_raw: ButtonsLayoutRaw,
+    #[serde(skip)]
+    pub buttons_layout: ButtonsLayout,
+
+    // File name of the chords dictionary. Chords are off if not set
+    #[serde(default)]
+    pub chords_dictionary: Option<String>,
+    #[serde(skip)]
+    pub chords_cfg: Option<ChordsConfigs>,
+}
+
+impl TypingConfigs {
+    pub fn load<P: AsRef<Path>>(configs_dir: P, typing_layout_name: &str) -> Result<Self> {
+        let typing_configs: Self = read_yaml(configs_dir.as_ref(), typing_layout_name)?;
+
+        let filepath = yaml_filepath(configs_dir.as_ref(), typing_layout_name);
+        let mut typing_configs = typing_configs
+            .load_sections()
+            .map_err(|err| locate_key_code_error(err, &[filepath]))?;
+
+        if let Some(dictionary_name) = &typing_configs.chords_dictionary {
+            typing_configs.chords_cfg = Some(
+                ChordsConfigs::load(configs_dir.as_ref(), dictionary_name)
+                    .wrap_err(format!("Chords dictionary '{}'", dictionary_name))?,
+            );
+        }
+        Ok(typing_configs)
+    }
+
+    fn load_sections(mut self) -> Result<Self> {
+        if !(self._start_threshold_pct < self._outer_threshold_pct
+            && self._outer_threshold_pct < 100)
+        {
+            bail!(
+                "Incorrect thresholds. Start: '{}', outer: '{}'",
+                self._start_threshold_pct,
+                self._outer_threshold_pct
+            )
+        }
+        self.start_threshold = convert_pct(self._start_threshold_pct);
+        self.outer_threshold = convert_pct(self._outer_threshold_pct);
+
+        self.left_pad.load("LeftPad")?;
+        self.right_pad.load("RightPad")?;
+        // Special buttons are not required
+        self.buttons_layout = ButtonsLayout::load(self._buttons_layout_raw.clone(), true)
+            .wrap_err("[ButtonsLayout]")?;
+        Ok(self)
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChordConfigs {
+    pub buttons: Vec<ButtonName>,
+    // Typed character by character
+    #[serde(default)]
+    pub text: Option<String>,
+    // Pressed together, like "KEY_LEFTCTRL", "KEY_BACKSPACE"
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ChordsConfigs {
+    // Used only for chords while typing
+    pub chord_btns: Vec<ButtonName>,
+    pub chords: Vec<ChordConfigs>,
+    #[serde(skip)]
+    pub dictionary: AHashMap<Chord, Vec<KeyCodes>>,
+}
+
+impl ChordsConfigs {
+    pub fn load<P: AsRef<Path>>(configs_dir: P, dictionary_name: &str) -> Result<Self> {
+        let mut chords_configs: Self = read_yaml(configs_dir.as_ref(), dictionary_name)?;
+        let filepath = yaml_filepath(configs_dir.as_ref(), dictionary_name);
+
+        for (chord_ind, chord_cfg) in chords_configs.chords.iter().enumerate() {
+            if chord_cfg.buttons.is_empty() {
+                bail!("Chord without buttons")
+            }
+            if let Some(button_name) = chord_cfg
+                .buttons
+                .iter()
+                .find(|button_name| !chords_configs.chord_btns.contains(button_name))
+            {
+                bail!(
+                    "Chord {:?}: '{}' is not in 'chord_btns'",
+                    chord_cfg.buttons,
+                    button_name
+                )
+            }
+
+            let key_strokes = match (&chord_cfg.text, chord_cfg.keys.is_empty()) {
+                (Some(text), true) => key_strokes_from_text(text)?,
+                (None, false) => {
+                    let context = format!("chords[{}].keys", chord_ind);
+                    let key_codes = key_codes_from_config(context.as_str(), &chord_cfg.keys)
+                        .map_err(|err| locate_key_code_error(err, &[filepath.clone()]))?;
+                    vec![key_codes]
+                }
+                (_, _) => bail!(
+                    "Chord {:?}: either 'text' or 'keys' has to be specified",
+                    chord_cfg.buttons
+                ),
+            };
+            let chord = chord_of(&chord_cfg.buttons);
+            if chords_configs
+                .dictionary
+                .insert(chord, key_strokes)
+                .is_some()
+            {
+                bail!("Chord {:?} is specified twice", chord_cfg.buttons)
+            }
+        }
+        Ok(chords_configs)
     }
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ButtonsLayout {
     pub reset_btn: ButtonName,
     pub switch_mode_btn: ButtonName,
+    pub layout_switches: AHashMap<ButtonName, LayoutSwitch>,
+    pub layer_switches: AHashMap<ButtonName, String>,
     //
     pub layout: AHashMap<ButtonName, KeyCodes>,
+    // Only buttons set in the layer. Others fall through to 'layout'
+    pub layers: AHashMap<String, AHashMap<ButtonName, KeyCodes>>,
+    // Tap codes of these buttons are in 'layout'
+    pub button_actions: AHashMap<ButtonName, ButtonActions>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TimedBinding {
+    pub key_codes: KeyCodes,
+    // Default one from [General] is used if not set
+    pub timeout: Option<Duration>,
+}
+
+fn timed_binding_from_config(
+    context: &str,
+    codes: &[String],
+    timeout_ms: Option<u16>,
+) -> Result<Option<TimedBinding>> {
+    let key_codes = key_codes_from_config(context, codes)?;
+    if key_codes.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(TimedBinding {
+        key_codes,
+        timeout: timeout_ms.map(|timeout_ms| Duration::from_millis(timeout_ms as u64)),
+    }))
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ButtonActions {
+    // Held after the hold time or once another button is pressed
+    pub hold: Option<TimedBinding>,
+    // Tapped once the button is held long enough
+    pub long_press: Option<TimedBinding>,
+    // Tapped on the second press. Single tap waits for it.
+    // Without hold and long press, the button held past its time presses the tap codes
+    pub double_tap: Option<TimedBinding>,
+}
+
+impl ButtonActions {
+    fn load(
+        section: &str,
+        button_name: ButtonName,
+        actions_raw: &ButtonActionsRaw,
+    ) -> Result<Self> {
+        let context = |action: &str| format!("{section}.{button_name}.{action}");
+        let button_actions = Self {
+            hold: timed_binding_from_config(
+                context("hold").as_str(),
+                &actions_raw.hold,
+                actions_raw.hold_ms,
+            )?,
+            long_press: timed_binding_from_config(
+                context("long_press").as_str(),
+                &actions_raw.long_press,
+                actions_raw.long_press_ms,
+            )?,
+            double_tap: timed_binding_from_config(
+                context("double_tap").as_str(),
+                &actions_raw.double_tap,
+                actions_raw.double_tap_ms,
+            )?,
+        };
+
+        if actions_raw.tap.is_empty() {
+            bail!("'{}': 'tap' has to be specified", button_name)
+        }
+        if button_actions.hold.is_some() && button_actions.long_press.is_some() {
+            bail!(
+                "'{}': 'hold' and 'long_press' cannot be combined",
+                button_name
+            )
+        }
+        if button_actions.hold.is_none()
+            && button_actions.long_press.is_none()
+            && button_actions.double_tap.is_none()
+        {
+            bail!(
+                "'{}': 'hold', 'long_press' or 'double_tap' has to be specified",
+                button_name
+            )
+        }
+        Ok(button_actions)
+    }
 }
 
 impl ButtonsLayout {
     pub fn load(layout_raw: ButtonsLayoutRaw, gaming_mode: bool) -> Result<Self> {
+        Self::load_section("ButtonsLayout", layout_raw, gaming_mode)
+    }
+
+    fn load_section(
+        section: &str,
+        layout_raw: ButtonsLayoutRaw,
+        gaming_mode: bool,
+    ) -> Result<Self> {
         let mut switch_mode_btn = ButtonName::DefaultForSpecialBtns;
         let mut reset_btn = ButtonName::DefaultForSpecialBtns;
+        let mut layout_switches: AHashMap<ButtonName, LayoutSwitch> = AHashMap::new();
+        let mut layer_switches: AHashMap<ButtonName, String> = AHashMap::new();
+        let mut button_actions: AHashMap<ButtonName, ButtonActions> = AHashMap::new();
 
         let mut layout: AHashMap<ButtonName, KeyCodes> = AHashMap::new();
 
-        let mut string_to_key_code = |button_name: ButtonName, codes: Vec<String>| -> Result<()> {
-            let mut key_codes = KeyCodes::new();
+        let mut string_to_key_code =
+            |button_name: ButtonName, binding: ButtonBindingRaw| -> Result<()> {
+                let (codes, has_actions) = match binding {
+                    ButtonBindingRaw::Codes(codes) => (codes, false),
+                    ButtonBindingRaw::Actions(actions_raw) => {
+                        let actions = ButtonActions::load(section, button_name, &actions_raw)?;
+                        button_actions.insert(button_name, actions);
+                        (actions_raw.tap, true)
+                    }
+                };
+                let mut key_codes = KeyCodes::new();
 
-            let detect_special = codes.len() == 1;
+                // Special codes can't be tapped
+                let detect_special = codes.len() == 1 && !has_actions;
 
-            for code_as_str in codes {
-                let key_code = key_code_from_config(
-                    button_name,
-                    code_as_str,
-                    &mut reset_btn,
-                    &mut switch_mode_btn,
-                    detect_special,
-                )?;
-                key_codes.push(key_code)
-            }
-            layout.insert(button_name, key_codes);
+                for code_as_str in codes {
+                    let key_code = key_code_from_config(
+                        section,
+                        button_name,
+                        code_as_str,
+                        &mut reset_btn,
+                        &mut switch_mode_btn,
+                        &mut layout_switches,
+                        &mut layer_switches,
+                        detect_special,
+                    )?;
+                    key_codes.push(key_code)
+                }
+                layout.insert(button_name, key_codes);
 
-            Ok(())
-        };
+                Ok(())
+            };
 
         string_to_key_code(ButtonName::BtnUp_SideL, layout_raw.BtnUp_SideL)?;
         string_to_key_code(ButtonName::BtnDown_SideL, layout_raw.BtnDown_SideL)?;
@@ -380,6 +1281,15 @@ impl ButtonsLayout {
         string_to_key_code(ButtonName::ExtraBtn_SideL, layout_raw.ExtraBtn_SideL)?;
         string_to_key_code(ButtonName::ExtraBtn_SideR, layout_raw.ExtraBtn_SideR)?;
         string_to_key_code(ButtonName::ExtraBtnCentral, layout_raw.ExtraBtnCentral)?;
+        string_to_key_code(ButtonName::Shake, layout_raw.Shake)?;
+        string_to_key_code(ButtonName::FlickUp, layout_raw.FlickUp)?;
+        string_to_key_code(ButtonName::FlickDown, layout_raw.FlickDown)?;
+        string_to_key_code(ButtonName::FlickLeft, layout_raw.FlickLeft)?;
+        string_to_key_code(ButtonName::FlickRight, layout_raw.FlickRight)?;
+        string_to_key_code(ButtonName::TiltHoldUp, layout_raw.TiltHoldUp)?;
+        string_to_key_code(ButtonName::TiltHoldDown, layout_raw.TiltHoldDown)?;
+        string_to_key_code(ButtonName::TiltHoldLeft, layout_raw.TiltHoldLeft)?;
+        string_to_key_code(ButtonName::TiltHoldRight, layout_raw.TiltHoldRight)?;
 
         if !gaming_mode {
             switch_mode_btn.bail_if_special_not_init()?;
@@ -390,76 +1300,409 @@ impl ButtonsLayout {
             //
             reset_btn,
             switch_mode_btn,
+            layout_switches,
+            layer_switches,
             //
             layout,
+            layers: AHashMap::new(),
+            button_actions,
         })
     }
+
+    pub fn load_layers(&mut self, layers_raw: &BTreeMap<String, ButtonsLayoutRaw>) -> Result<()> {
+        for (layer_name, layer_raw) in layers_raw {
+            let section = format!("Layers.{layer_name}");
+            let layer = Self::load_section(section.as_str(), layer_raw.clone(), true)?;
+            if layer.reset_btn != ButtonName::DefaultForSpecialBtns
+                || layer.switch_mode_btn != ButtonName::DefaultForSpecialBtns
+                || !layer.layout_switches.is_empty()
+                || !layer.layer_switches.is_empty()
+                || !layer.button_actions.is_empty()
+            {
+                bail!(
+                    "'{}': special buttons, layout and layer switches, button actions are only allowed in [ButtonsLayout]",
+                    layer_name
+                )
+            }
+            let layer = layer
+                .layout
+                .into_iter()
+                .filter(|(_, key_codes)| !key_codes.is_empty())
+                .collect();
+            self.layers.insert(layer_name.clone(), layer);
+        }
+
+        for (button_name, layer_name) in &self.layer_switches {
+            if !self.layers.contains_key(layer_name) {
+                bail!("'{}': layer '{}' is not found", button_name, layer_name)
+            }
+        }
+        Ok(())
+    }
+}
+
+// List of codes or codes per action
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum ButtonBindingRaw {
+    Codes(Vec<String>),
+    Actions(ButtonActionsRaw),
+}
+
+// Picked by the YAML type. Untagged enums drop the error of the variant with its position
+impl<'de> Deserialize<'de> for ButtonBindingRaw {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct BindingVisitor;
+
+        impl<'de> Visitor<'de> for BindingVisitor {
+            type Value = ButtonBindingRaw;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a list of key codes or a map of actions")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                Vec::deserialize(SeqAccessDeserializer::new(seq)).map(ButtonBindingRaw::Codes)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                ButtonActionsRaw::deserialize(MapAccessDeserializer::new(map))
+                    .map(ButtonBindingRaw::Actions)
+            }
+        }
+
+        deserializer.deserialize_any(BindingVisitor)
+    }
+}
+
+impl Default for ButtonBindingRaw {
+    fn default() -> Self {
+        ButtonBindingRaw::Codes(vec![])
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonActionsRaw {
+    // Sent on release if no other action happened. Required, checked on load
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub tap: Vec<String>,
+    // Held while the button is, after the hold time or once another button is pressed
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub hold: Vec<String>,
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub long_press: Vec<String>,
+    #[serde(default)]
+    #[schemars(schema_with = "key_codes_schema")]
+    pub double_tap: Vec<String>,
+    // Override the times from [General]
+    #[serde(default)]
+    pub hold_ms: Option<u16>,
+    #[serde(default)]
+    pub long_press_ms: Option<u16>,
+    #[serde(default)]
+    pub double_tap_ms: Option<u16>,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct ButtonsLayoutRaw {
     #[serde(default)]
-    pub BtnUp_SideL: Vec<String>,
+    pub BtnUp_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub BtnDown_SideL: Vec<String>,
+    pub BtnDown_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub BtnLeft_SideL: Vec<String>,
+    pub BtnLeft_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub BtnRight_SideL: Vec<String>,
+    pub BtnRight_SideL: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub BtnUp_SideR: Vec<String>,
+    pub BtnUp_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub BtnDown_SideR: Vec<String>,
+    pub BtnDown_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub BtnLeft_SideR: Vec<String>,
+    pub BtnLeft_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub BtnRight_SideR: Vec<String>,
+    pub BtnRight_SideR: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub Wing_SideL: Vec<String>,
+    pub Wing_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub Wing_SideR: Vec<String>,
+    pub Wing_SideR: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub LowerTriggerAsBtn_SideL: Vec<String>,
+    pub LowerTriggerAsBtn_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub LowerTriggerAsBtn_SideR: Vec<String>,
+    pub LowerTriggerAsBtn_SideR: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub UpperTrigger_SideL: Vec<String>,
+    pub UpperTrigger_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub UpperTrigger_SideR: Vec<String>,
+    pub UpperTrigger_SideR: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub PadAsBtn_SideL: Vec<String>,
+    pub PadAsBtn_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub PadAsBtn_SideR: Vec<String>,
+    pub PadAsBtn_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub StickAsBtn: Vec<String>,
+    pub StickAsBtn: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub PadUp_SideL: Vec<String>,
+    pub PadUp_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub PadDown_SideL: Vec<String>,
+    pub PadDown_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub PadLeft_SideL: Vec<String>,
+    pub PadLeft_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub PadRight_SideL: Vec<String>,
+    pub PadRight_SideL: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub PadUp_SideR: Vec<String>,
+    pub PadUp_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub PadDown_SideR: Vec<String>,
+    pub PadDown_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub PadLeft_SideR: Vec<String>,
+    pub PadLeft_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub PadRight_SideR: Vec<String>,
+    pub PadRight_SideR: ButtonBindingRaw,
     //
     #[serde(default)]
-    pub ExtraBtn_SideL: Vec<String>,
+    pub ExtraBtn_SideL: ButtonBindingRaw,
     #[serde(default)]
-    pub ExtraBtn_SideR: Vec<String>,
+    pub ExtraBtn_SideR: ButtonBindingRaw,
     #[serde(default)]
-    pub ExtraBtnCentral: Vec<String>,
+    pub ExtraBtnCentral: ButtonBindingRaw,
+    // Motion gestures
+    #[serde(default)]
+    pub Shake: ButtonBindingRaw,
+    #[serde(default)]
+    pub FlickUp: ButtonBindingRaw,
+    #[serde(default)]
+    pub FlickDown: ButtonBindingRaw,
+    #[serde(default)]
+    pub FlickLeft: ButtonBindingRaw,
+    #[serde(default)]
+    pub FlickRight: ButtonBindingRaw,
+    #[serde(default)]
+    pub TiltHoldUp: ButtonBindingRaw,
+    #[serde(default)]
+    pub TiltHoldDown: ButtonBindingRaw,
+    #[serde(default)]
+    pub TiltHoldLeft: ButtonBindingRaw,
+    #[serde(default)]
+    pub TiltHoldRight: ButtonBindingRaw,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+
+    // configs.yaml before the MotionSensor, AppRules and backend sections
+    const BASELINE_CONFIGS: &str = r#"
+Layouts:
+  #  buttons_layout: "desktop"
+  buttons_layout: "raft"
+  # buttons_layout: "risk"
+  #  buttons_layout: "wmd"
+  #  buttons_layout: "valheim"
+  #  buttons_layout: "satisfactory"
+
+  typing_layout: "typing"
+General:
+  steamy_channel_size: 100
+  commands_channel_size: 100
+  steamy_read_interrupt_interval: 0
+  input_raw_refresh_interval: 500
+  input_buffer_refresh_interval: 2
+  mouse_refresh_interval: 1
+
+is_left_pad_bytes_dump: false
+"#;
+
+    fn temp_configs_dir(test_name: &str) -> PathBuf {
+        let configs_dir = temp_dir().join(format!(
+            "joystick-configs-{}-{}",
+            test_name,
+            std::process::id()
+        ));
+        create_dir_all(&configs_dir).unwrap();
+        configs_dir
+    }
+
+    #[test]
+    fn baseline_configs_load_with_defaults() -> Result<()> {
+        let configs_dir = temp_configs_dir("baseline");
+        write(yaml_filepath(&configs_dir, "configs"), BASELINE_CONFIGS)?;
+        let result = MainConfigs::load_main(configs_dir.clone());
+        remove_dir_all(&configs_dir)?;
+        let main_configs = result?;
+
+        assert_eq!(main_configs.layout_names_cfg.buttons_layout_name, "raft");
+        assert_eq!(main_configs.backend, InputBackendName::Auto);
+
+        let motion_sensor_cfg = main_configs.motion_sensor_cfg;
+        assert_eq!(
+            motion_sensor_cfg.calibration_duration,
+            Duration::from_millis(2000)
+        );
+        assert_eq!(
+            motion_sensor_cfg.calibration_timeout,
+            Duration::from_millis(15000)
+        );
+        assert_eq!(
+            motion_sensor_cfg.still_duration,
+            Duration::from_millis(1000)
+        );
+        assert!(motion_sensor_cfg.drift_correction);
+        Ok(())
+    }
+
+    // Desktop layout with one binding replaced
+    fn load_edited_desktop(test_name: &str, binding: &str) -> Result<LayoutConfigs> {
+        let repo_layouts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/layouts");
+        let content = read_to_string(yaml_filepath(&repo_layouts_dir, "desktop"))?;
+        let original = r#"  BtnDown_SideR: [ "KEY_DOWN" ]"#;
+        assert!(content.contains(original));
+
+        let layouts_dir = temp_configs_dir(test_name);
+        write(
+            yaml_filepath(&layouts_dir, "edited"),
+            content.replacen(original, binding, 1),
+        )?;
+        let result = LayoutConfigs::load("edited", &layouts_dir);
+        remove_dir_all(&layouts_dir)?;
+        result
+    }
+
+    #[test]
+    fn misspelled_action_is_reported_with_its_position() {
+        let err = load_edited_desktop(
+            "misspelled-action",
+            r#"  BtnDown_SideR: { tap: [ "KEY_DOWN" ], doubel_tap: [ "KEY_UP" ] }"#,
+        )
+        .unwrap_err();
+        let message = format!("{:?}", err);
+        assert!(
+            message.contains("unknown field `doubel_tap`"),
+            "{}",
+            message
+        );
+        assert!(message.contains("line 4"), "{}", message);
+    }
+
+    #[test]
+    fn actions_without_tap_are_reported() {
+        let err = load_edited_desktop(
+            "missing-tap",
+            r#"  BtnDown_SideR: { hold: [ "KEY_LEFTSHIFT" ] }"#,
+        )
+        .unwrap_err();
+        let message = format!("{:?}", err);
+        assert!(
+            message.contains("'BtnDown_SideR': 'tap' has to be specified"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn repo_chords_dictionary_loads() -> Result<()> {
+        let configs_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config");
+        let chords_cfg = ChordsConfigs::load(configs_dir, "chords")?;
+
+        let dictionary = &chords_cfg.dictionary;
+        assert_eq!(dictionary.len(), chords_cfg.chords.len());
+        assert_eq!(
+            dictionary[&chord_of(&[ButtonName::BtnRight_SideR, ButtonName::UpperTrigger_SideR])],
+            [vec![KeyCode::KEY_LEFTCTRL, KeyCode::KEY_BACKSPACE]]
+        );
+        // Any order of buttons is the same chord
+        assert_eq!(
+            dictionary[&chord_of(&[ButtonName::Wing_SideL, ButtonName::UpperTrigger_SideL])],
+            [
+                vec![KeyCode::KEY_LEFTSHIFT, KeyCode::KEY_I],
+                vec![KeyCode::KEY_SPACE]
+            ]
+        );
+        Ok(())
+    }
+
+    fn load_chords(test_name: &str, chords: &str) -> Result<ChordsConfigs> {
+        let configs_dir = temp_configs_dir(test_name);
+        let content = format!(
+            "chord_btns: [ \"BtnDown_SideR\", \"BtnLeft_SideR\" ]\nchords:\n{}",
+            chords
+        );
+        write(yaml_filepath(&configs_dir, "chords"), content)?;
+        let result = ChordsConfigs::load(&configs_dir, "chords");
+        remove_dir_all(&configs_dir)?;
+        result
+    }
+
+    fn chords_error(test_name: &str, chords: &str) -> String {
+        match load_chords(test_name, chords) {
+            Ok(_) => panic!("Chords '{}' are loaded", chords),
+            Err(err) => format!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn incorrect_chords_are_reported() {
+        let cases = [
+            (
+                "no-buttons",
+                "  - buttons: []\n    text: \"a\"\n",
+                "Chord without buttons",
+            ),
+            (
+                "not-chord-btn",
+                "  - buttons: [ \"Wing_SideR\" ]\n    text: \"a\"\n",
+                "Chord [Wing_SideR]: 'Wing_SideR' is not in 'chord_btns'",
+            ),
+            (
+                "text-and-keys",
+                "  - buttons: [ \"BtnDown_SideR\" ]\n    text: \"a\"\n    keys: [ \"KEY_A\" ]\n",
+                "Chord [BtnDown_SideR]: either 'text' or 'keys' has to be specified",
+            ),
+            (
+                "twice",
+                "  - buttons: [ \"BtnDown_SideR\", \"BtnLeft_SideR\" ]\n    text: \"a\"\n  - buttons: [ \"BtnLeft_SideR\", \"BtnDown_SideR\" ]\n    text: \"b\"\n",
+                "Chord [BtnLeft_SideR, BtnDown_SideR] is specified twice",
+            ),
+            (
+                "untypable",
+                "  - buttons: [ \"BtnDown_SideR\" ]\n    text: \"é\"\n",
+                "Character 'é' of 'é' can't be typed",
+            ),
+            (
+                "unknown-field",
+                "  - buttons: [ \"BtnDown_SideR\" ]\n    txt: \"a\"\n",
+                "unknown field `txt`",
+            ),
+        ];
+        for (test_name, chords, expected) in cases {
+            let message = chords_error(test_name, chords);
+            assert!(message.contains(expected), "{}", message);
+        }
+    }
+
+    #[test]
+    fn misspelled_chord_key_is_reported_with_its_position() {
+        let message = chords_error(
+            "misspelled-key",
+            "  - buttons: [ \"BtnDown_SideR\" ]\n    text: \"a\"\n  - buttons: [ \"BtnLeft_SideR\" ]\n    keys: [ \"KEY_SPACEE\" ]\n",
+        );
+        assert!(message.contains("KEY_SPACEE"), "{}", message);
+        assert!(message.contains("chords.yaml:6:"), "{}", message);
+    }
 }
@@ -0,0 +1,264 @@
+use crate::file_ops::read_yaml;
+use crate::match_event::{AxisName, ButtonName};
+use ahash::{AHashMap, AHashSet};
+use color_eyre::eyre::{OptionExt, Result};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fs::read_dir;
+use std::path::Path;
+
+// Order matters: the first profile whose gamepad name matches is used
+const BUILT_IN_PROFILES: [(&str, &str); 3] = [
+    ("steam", include_str!("../config/devices/steam.yaml")),
+    ("xbox", include_str!("../config/devices/xbox.yaml")),
+    (
+        "dualshock",
+        include_str!("../config/devices/dualshock.yaml"),
+    ),
+];
+// Most pads follow the same evdev codes as Xbox ones
+const FALLBACK_PROFILE: &str = "xbox";
+
+// Maps raw evdev codes reported by gilrs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    #[serde(skip)]
+    pub name: String,
+    // Case-insensitive parts of the gamepad name
+    #[serde(default)]
+    pub gamepad_names: Vec<String>,
+    pub buttons: AHashMap<u16, ButtonName>,
+    pub axes: AHashMap<u16, AxisName>,
+    #[serde(default)]
+    pub inverted_axes: Vec<AxisName>,
+
+    // Every unknown code is reported only once
+    #[serde(skip)]
+    reported_codes: AHashSet<u16>,
+}
+
+impl DeviceProfile {
+    fn matches(&self, gamepad_name: &str) -> bool {
+        let gamepad_name = gamepad_name.to_lowercase();
+        self.gamepad_names
+            .iter()
+            .any(|name| gamepad_name.contains(name.to_lowercase().as_str()))
+    }
+
+    fn report_unknown(&mut self, kind: &str, code: u16) {
+        if self.reported_codes.insert(code) {
+            warn!(
+                "Unknown {} code {} in '{}' device profile. Ignored",
+                kind, code, self.name
+            );
+        }
+    }
+
+    pub fn match_button(&mut self, code: u16) -> Option<ButtonName> {
+        let button = self.buttons.get(&code).copied();
+        if button.is_none() {
+            self.report_unknown("button", code);
+        }
+        button
+    }
+
+    pub fn match_axis(&mut self, code: u16) -> Option<AxisName> {
+        let axis = self.axes.get(&code).copied();
+        if axis.is_none() {
+            self.report_unknown("axis", code);
+        }
+        axis
+    }
+
+    pub fn is_inverted(&self, axis: AxisName) -> bool {
+        self.inverted_axes.contains(&axis)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DeviceProfiles {
+    profiles: Vec<DeviceProfile>,
+}
+
+impl DeviceProfiles {
+    // Files from the folder override built-in profiles with the same name.
+    // Custom profiles are matched before built-in ones
+    pub fn load<P: AsRef<Path>>(devices_dir: P) -> Result<Self> {
+        let mut profiles: Vec<DeviceProfile> = vec![];
+        let mut overrides: AHashMap<String, DeviceProfile> = AHashMap::new();
+
+        if devices_dir.as_ref().is_dir() {
+            let mut filepaths: Vec<_> = read_dir(devices_dir.as_ref())?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            filepaths.sort();
+
+            for filepath in filepaths {
+                if filepath.extension().is_none_or(|ext| ext != "yaml") {
+                    continue;
+                }
+                let name = filepath
+                    .file_stem()
+                    .and_then(|name| name.to_str())
+                    .ok_or_eyre("Invalid device profile filename")?;
+
+                let mut profile: DeviceProfile = read_yaml(devices_dir.as_ref(), name)?;
+                profile.name = name.to_string();
+
+                if BUILT_IN_PROFILES
+                    .iter()
+                    .any(|(built_in, _)| *built_in == name)
+                {
+                    overrides.insert(profile.name.clone(), profile);
+                } else {
+                    profiles.push(profile);
+                }
+            }
+        }
+
+        for (name, content) in BUILT_IN_PROFILES {
+            let profile = match overrides.remove(name) {
+                Some(profile) => profile,
+                None => {
+                    let mut profile: DeviceProfile = serde_yml::from_str(content)?;
+                    profile.name = name.to_string();
+                    profile
+                }
+            };
+            profiles.push(profile);
+        }
+
+        Ok(Self { profiles })
+    }
+
+    pub fn get(&self, name: &str) -> Result<DeviceProfile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.name == name)
+            .cloned()
+            .ok_or_eyre(format!("Device profile '{}' is not found", name))
+    }
+
+    pub fn select(&self, profile_name: Option<&str>, gamepad_name: &str) -> Result<DeviceProfile> {
+        if let Some(profile_name) = profile_name {
+            return self.get(profile_name);
+        }
+
+        match self
+            .profiles
+            .iter()
+            .find(|profile| profile.matches(gamepad_name))
+        {
+            Some(profile) => Ok(profile.clone()),
+            None => {
+                debug!(
+                    "No device profile for '{}'. Using '{}'",
+                    gamepad_name, FALLBACK_PROFILE
+                );
+                self.get(FALLBACK_PROFILE)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::PathBuf;
+
+    fn temp_devices_dir(test_name: &str) -> PathBuf {
+        temp_dir().join(format!(
+            "joystick-devices-{}-{}",
+            test_name,
+            std::process::id()
+        ))
+    }
+
+    fn selected_name(profiles: &DeviceProfiles, gamepad_name: &str) -> String {
+        profiles.select(None, gamepad_name).unwrap().name
+    }
+
+    #[test]
+    fn built_in_profiles_are_matched_by_gamepad_name() -> Result<()> {
+        // No devices folder
+        let profiles = DeviceProfiles::load(temp_devices_dir("built-in"))?;
+
+        assert_eq!(selected_name(&profiles, "Steam Controller"), "steam");
+        assert_eq!(selected_name(&profiles, "Microsoft X-Box 360 pad"), "xbox");
+        assert_eq!(
+            selected_name(
+                &profiles,
+                "Sony Interactive Entertainment Wireless Controller"
+            ),
+            "dualshock"
+        );
+        assert_eq!(selected_name(&profiles, "Generic USB Joystick"), "xbox");
+
+        let profile = profiles.select(Some("dualshock"), "Steam Controller")?;
+        assert_eq!(profile.name, "dualshock");
+        assert_eq!(profile.buttons[&307], ButtonName::BtnUp_SideR);
+        assert_eq!(profile.axes[&5], AxisName::LowerTrigger_SideR);
+        assert!(profile.is_inverted(AxisName::PadY_SideL));
+        assert!(profiles
+            .select(Some("missing"), "Steam Controller")
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn files_in_devices_folder_override_built_in_profiles() -> Result<()> {
+        let devices_dir = temp_devices_dir("overrides");
+        create_dir_all(&devices_dir)?;
+        write(
+            devices_dir.join("xbox.yaml"),
+            "gamepad_names: [\"xbox\"]\nbuttons:\n  304: BtnRight_SideR\naxes: {}\n",
+        )?;
+        write(
+            devices_dir.join("elite.yaml"),
+            "gamepad_names: [\"xbox elite\"]\nbuttons:\n  704: Wing_SideL\naxes: {}\n",
+        )?;
+        write(devices_dir.join("notes.txt"), "Not a profile")?;
+
+        let result = DeviceProfiles::load(&devices_dir);
+        remove_dir_all(&devices_dir)?;
+        let profiles = result?;
+
+        // Custom profiles go before built-in ones
+        assert_eq!(
+            selected_name(&profiles, "Xbox Elite Wireless Controller"),
+            "elite"
+        );
+        let profile = profiles.select(None, "Microsoft X-Box 360 pad")?;
+        assert_eq!(profile.name, "xbox");
+        assert_eq!(profile.buttons.len(), 1);
+        assert_eq!(profile.buttons[&304], ButtonName::BtnRight_SideR);
+        // The fallback is the overridden profile too
+        assert_eq!(profiles.get(FALLBACK_PROFILE)?.buttons.len(), 1);
+        // Not overridden
+        assert_eq!(
+            profiles.get("dualshock")?.buttons[&304],
+            ButtonName::BtnDown_SideR
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_codes_are_reported_once_and_ignored() -> Result<()> {
+        let profiles = DeviceProfiles::load(temp_devices_dir("unknown-codes"))?;
+        let mut profile = profiles.get("xbox")?;
+
+        assert_eq!(profile.match_button(999), None);
+        assert_eq!(profile.match_button(999), None);
+        assert_eq!(profile.reported_codes.len(), 1);
+        assert_eq!(profile.match_axis(42), None);
+        assert_eq!(profile.reported_codes.len(), 2);
+
+        // Known codes still work
+        assert_eq!(profile.match_button(304), Some(ButtonName::BtnDown_SideR));
+        assert_eq!(profile.match_axis(0), Some(AxisName::StickX));
+        assert_eq!(profile.reported_codes.len(), 2);
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
 use crate::exec_or_eyre;
-use color_eyre::eyre::{bail, eyre, OptionExt, Result};
+use color_eyre::eyre::{bail, eyre, OptionExt, Result, WrapErr};
 use homedir::my_home;
 use std::env::current_dir;
-use std::fs::read_to_string;
+use std::fs::{create_dir_all, read_to_string, write};
 use std::path::{Path, PathBuf};
 
 pub fn get_home_dir() -> Result<PathBuf> {
@@ -33,24 +33,43 @@ pub fn get_project_dir(project_name: &str) -> Result<PathBuf> {
     Ok(cur_dir)
 }
 
-pub fn read_yaml<T, P, S>(folder: P, filename: S) -> Result<T>
-where
-    T: serde::de::DeserializeOwned,
-    P: AsRef<Path>,
-    S: AsRef<str>,
-{
+pub fn yaml_filepath<P: AsRef<Path>, S: AsRef<str>>(folder: P, filename: S) -> PathBuf {
     const EXTENSION: &str = ".yaml";
     let mut filename = filename.as_ref().to_string();
     if !filename.ends_with(EXTENSION) {
         filename += EXTENSION
     }
+    folder.as_ref().join(filename)
+}
 
-    let filepath = folder.as_ref().join(filename);
-    let file_content = read_to_string(filepath)?;
-    let decoded_obj = serde_yml::from_str(file_content.as_str())?;
+pub fn read_yaml<T, P, S>(folder: P, filename: S) -> Result<T>
+where
+    T: serde::de::DeserializeOwned,
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    let filepath = yaml_filepath(folder, filename);
+    let file_content =
+        read_to_string(&filepath).wrap_err(format!("Cannot read '{}'", filepath.display()))?;
+    // Parse errors contain the line and the column
+    let decoded_obj =
+        serde_yml::from_str(file_content.as_str()).wrap_err(format!("{}", filepath.display()))?;
     Ok(decoded_obj)
 }
 
+pub fn write_yaml<T, P, S>(folder: P, filename: S, obj: &T) -> Result<()>
+where
+    T: serde::Serialize,
+    P: AsRef<Path>,
+    S: AsRef<str>,
+{
+    create_dir_all(folder.as_ref())?;
+    let filepath = yaml_filepath(folder, filename);
+    let encoded_obj = serde_yml::to_string(obj)?;
+    write(filepath, encoded_obj)?;
+    Ok(())
+}
+
 // pub fn read_configs<T, P, S>(folder: P, filename: S) -> Result<T>
 // where
 //     T: serde::de::DeserializeOwned,
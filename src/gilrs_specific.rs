@@ -1,13 +1,19 @@
-use crate::configs::MainConfigs;
+use crate::configs::{InputBackendName, MainConfigs};
+use crate::device_profile::{DeviceProfile, DeviceProfiles};
 use crate::exec_or_eyre;
+use crate::input_backend::InputBackend;
+use crate::key_codes::LayoutSwitch;
 use crate::match_event::{AxisName, ButtonName, EventTypeName, TransformStatus, TransformedEvent};
 use crate::process_event::{process_event, ImplementationSpecificCfg, SharedInfo};
-use crate::utils::TerminationStatus;
+use crate::process_watcher::spawn_process_watcher;
+use crate::utils::{check_thread_handle, create_channel, TerminationStatus, ThreadHandle};
+use crate::writing_thread::spawn_writing_thread;
+use ahash::AHashMap;
 use color_eyre::eyre::{bail, OptionExt, Result};
 use gilrs::ev::Code;
 use gilrs::EventType::Disconnected;
-use gilrs::{Axis, Button, Event, EventType, EventType::*, Filter, Gamepad, Gilrs};
-use log::debug;
+use gilrs::{Axis, Button, Event, EventType, EventType::*, Filter, Gamepad, GamepadId, Gilrs};
+use log::{debug, warn};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::thread::sleep;
@@ -17,6 +23,8 @@ const VENDOR_ID: u16 = 0x28de;
 const PRODUCT_ID: [u16; 2] = [0x1102, 0x1142];
 const ENDPOINT: [u8; 2] = [3, 2];
 const INDEX: [u16; 2] = [2, 1];
+// For analog values of codes that are listed as buttons
+const BUTTON_PRESS_THRESHOLD: f32 = 0.5;
 
 struct UsbHolder {
     device: rusb::Device<rusb::GlobalContext>,
@@ -123,148 +131,269 @@ pub fn print_deadzones(gilrs: &Gilrs, id: usize) -> Result<()> {
     Ok(())
 }
 
-fn read_events(
-    gilrs: &mut Gilrs,
-    shared_info: &SharedInfo,
-    configs: &MainConfigs,
-    termination_status: &TerminationStatus,
-) -> Result<()> {
-    let impl_cfg = ImplementationSpecificCfg::new(-1.0, 1.0);
+struct GamepadPipeline {
+    shared_info: SharedInfo,
+    device_profile: DeviceProfile,
+    layout_name: String,
+    follows_app_rules: bool,
+    stop_status: TerminationStatus,
+    writing_thread: ThreadHandle,
+}
 
-    let input_buffer_refresh_interval = configs.general.input_buffer_refresh_interval;
+impl GamepadPipeline {
+    fn new(
+        layout_name: &str,
+        follows_app_rules: bool,
+        device_profile: DeviceProfile,
+        configs: &MainConfigs,
+    ) -> Result<Self> {
+        let configs = configs.with_layout(layout_name)?;
+        let shared_info = SharedInfo::new(&configs)?;
+        // Each gamepad has its own writing thread that is stopped separately
+        let stop_status = TerminationStatus::default();
+        let writing_thread = spawn_writing_thread(&shared_info, &configs, &stop_status);
+
+        Ok(Self {
+            shared_info,
+            device_profile,
+            layout_name: layout_name.to_string(),
+            follows_app_rules,
+            stop_status,
+            writing_thread,
+        })
+    }
 
-    // gilrs.next_event().filter_ev()
-    print_deadzones(gilrs, 0)?;
+    fn switch_by_app_rules(&self, layout_switch: &LayoutSwitch) -> Result<()> {
+        if self.follows_app_rules {
+            self.shared_info
+                .layout_switch_sender()
+                .send(layout_switch.clone())?;
+        }
+        Ok(())
+    }
 
-    loop {
-        if termination_status.check() {
-            return Ok(());
-        };
+    fn stop(self) -> Result<()> {
+        self.stop_status.terminate()
+    }
+}
 
-        // Examine new events
-        while let Some(Event {
-                           id, event, time, ..
-                       }) = gilrs.next_event()
+fn format_uuid(uuid: [u8; 16]) -> String {
+    let hex: String = uuid.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+fn connect_gamepad(
+    gilrs: &Gilrs,
+    id: GamepadId,
+    configs: &MainConfigs,
+    device_profiles: &DeviceProfiles,
+    pipelines: &mut AHashMap<GamepadId, GamepadPipeline>,
+    app_layout_switch: Option<&LayoutSwitch>,
+) -> Result<()> {
+    let gamepad = gilrs.gamepad(id);
+    let uuid = format_uuid(gamepad.uuid());
+    let layout_name = configs
+        .layout_names_cfg
+        .gamepad_layout_name(gamepad.name(), uuid.as_str());
+    let follows_app_rules = configs
+        .layout_names_cfg
+        .explicit_gamepad_layout_name(gamepad.name(), uuid.as_str())
+        .is_none();
+    let device_profile = device_profiles.select(
+        configs
+            .layout_names_cfg
+            .gamepad_profile_name(gamepad.name(), uuid.as_str()),
+        gamepad.name(),
+    )?;
+
+    println!(
+        "id {}: {} ({}) is {:?}",
+        id,
+        gamepad.name(),
+        uuid,
+        gamepad.power_info()
+    );
+
+    // Reconnected gamepad keeps its pipeline
+    if let Some(pipeline) = pipelines.get(&id) {
+        if pipeline.layout_name == layout_name
+            && pipeline.device_profile.name == device_profile.name
         {
-            let is_disconnected = event == Disconnected;
-            debug!("{}", print_event(&event)?);
+            println!("Gamepad {} reconnected", id);
+            return Ok(());
+        }
+    }
+    if let Some(pipeline) = pipelines.remove(&id) {
+        pipeline.stop()?;
+    }
 
-            let event = normalize_event(&event, shared_info.RESET_BTN)?;
-            process_event(event, shared_info, &impl_cfg)?;
+    if let Err(err) = print_deadzones(gilrs, id.into()) {
+        warn!("Cannot print deadzones: {}", err);
+    }
 
-            if is_disconnected {
-                shared_info.release_all_hard()?;
-                println!("Gamepad disconnected");
-                return Ok(());
-            }
-        }
-        sleep(input_buffer_refresh_interval);
-        // sleep(Duration::from_millis(4)); //4 = USB min latency
+    println!(
+        "Gamepad {} connected. Layout: {}. Device profile: {}",
+        id, layout_name, device_profile.name
+    );
+    let pipeline = GamepadPipeline::new(layout_name, follows_app_rules, device_profile, configs)?;
+    // App that is already running
+    if let Some(layout_switch) = app_layout_switch {
+        pipeline.switch_by_app_rules(layout_switch)?;
     }
+    pipelines.insert(id, pipeline);
+    Ok(())
 }
 
 fn init_gilrs() -> Result<Gilrs> {
     exec_or_eyre!(Gilrs::new())
 }
 
-pub fn run_gilrs_loop(
-    shared_info: &SharedInfo,
-    configs: &MainConfigs,
-    termination_status: &TerminationStatus,
-) -> Result<()> {
+pub struct GilrsBackend;
+
+impl InputBackend for GilrsBackend {
+    fn name(&self) -> InputBackendName {
+        InputBackendName::Gilrs
+    }
+
+    fn is_available(&mut self) -> bool {
+        match init_gilrs() {
+            Ok(gilrs) => gilrs.gamepads().count() > 0,
+            Err(_) => false,
+        }
+    }
+
+    fn run(&mut self, configs: &MainConfigs, termination_status: &TerminationStatus) -> Result<()> {
+        run_gilrs_loop(configs, termination_status)
+    }
+}
+
+pub fn run_gilrs_loop(configs: &MainConfigs, termination_status: &TerminationStatus) -> Result<()> {
     // let usb_holder = find_usb_device()?;
 
+    let impl_cfg = ImplementationSpecificCfg::new(-1.0, 1.0, true);
+    let input_buffer_refresh_interval = configs.general.input_buffer_refresh_interval;
+
+    let device_profiles = DeviceProfiles::load(configs.devices_dir())?;
     let mut gilrs = init_gilrs()?;
+    let mut pipelines: AHashMap<GamepadId, GamepadPipeline> = AHashMap::new();
+
+    // Single watcher for all gamepads
+    let (app_switch_sender, app_switch_receiver) = create_channel(-1);
+    let process_watcher = spawn_process_watcher(app_switch_sender, configs, termination_status);
+    let mut app_layout_switch: Option<LayoutSwitch> = None;
+
+    let connected_ids: Vec<GamepadId> = gilrs.gamepads().map(|(id, _)| id).collect();
+    for id in connected_ids {
+        connect_gamepad(&gilrs, id, configs, &device_profiles, &mut pipelines, None)?;
+    }
+    if pipelines.is_empty() {
+        println!("Gamepad is not connected. Waiting...");
+    }
 
-    let mut wait_msg_is_printed = false;
     loop {
-        gilrs = init_gilrs()?;
-        for (id, gamepad) in gilrs.gamepads() {
-            println!(
-                "id {}: {} is {:?}",
-                id,
-                gamepad.name(),
-                gamepad.power_info()
-            );
-        }
+        if termination_status.check() {
+            for (_, pipeline) in pipelines.drain() {
+                pipeline.stop()?;
+            }
+            return Ok(());
+        };
 
-        match gilrs.gamepads().count() {
-            0 => {
-                if !wait_msg_is_printed {
-                    wait_msg_is_printed = true;
-                    println!("Gamepad is not connected. Waiting...");
-                }
+        check_thread_handle(process_watcher.as_ref())?;
+        for layout_switch in app_switch_receiver.try_iter() {
+            for pipeline in pipelines.values() {
+                pipeline.switch_by_app_rules(&layout_switch)?;
             }
-            1 => {
-                println!("Gamepad connected");
-                wait_msg_is_printed = false;
-                read_events(&mut gilrs, shared_info, configs, termination_status)?;
+            app_layout_switch = Some(layout_switch);
+        }
+
+        for pipeline in pipelines.values_mut() {
+            check_thread_handle(Some(&pipeline.writing_thread))?;
+            pipeline.shared_info.update_layout()?;
+        }
+
+        // Examine new events
+        while let Some(Event { id, event, .. }) = gilrs.next_event() {
+            debug!("{}", print_event(&event)?);
+
+            if event == Connected {
+                connect_gamepad(
+                    &gilrs,
+                    id,
+                    configs,
+                    &device_profiles,
+                    &mut pipelines,
+                    app_layout_switch.as_ref(),
+                )?;
+                continue;
             }
-            _ => {
-                println!("Only one gamepad is supported. Disconnect other gamepads");
+
+            let Some(pipeline) = pipelines.get_mut(&id) else {
+                continue;
+            };
+            let shared_info = &pipeline.shared_info;
+
+            let is_disconnected = event == Disconnected;
+
+            let event =
+                normalize_event(&event, &mut pipeline.device_profile, shared_info.RESET_BTN)?;
+            process_event(event, shared_info, &impl_cfg)?;
+
+            // Other gamepads keep their state
+            if is_disconnected {
+                shared_info.release_all_hard()?;
+                println!("Gamepad {} disconnected", id);
             }
         }
-        sleep(Duration::from_millis(5000));
+        sleep(input_buffer_refresh_interval);
+        // sleep(Duration::from_millis(4)); //4 = USB min latency
     }
 }
 
-pub fn match_button(code: u16) -> Result<ButtonName> {
-    Ok(match code {
-        304 => ButtonName::BtnDown_SideR,
-        305 => ButtonName::BtnRight_SideR,
-        308 => ButtonName::BtnUp_SideR,
-        307 => ButtonName::BtnLeft_SideR,
-        //
-        336 => ButtonName::Wing_SideL,
-        337 => ButtonName::Wing_SideR,
-        //
-        289 => ButtonName::PadAsTouch_SideL,
-        290 => ButtonName::PadAsTouch_SideR,
-        318 => ButtonName::PadAsBtn_SideR,
-        317 => ButtonName::StickAsBtn,
-        //
-        545 => ButtonName::PadDown_SideL,
-        547 => ButtonName::PadRight_SideL,
-        544 => ButtonName::PadUp_SideL,
-        546 => ButtonName::PadLeft_SideL,
-        //
-        312 => ButtonName::LowerTriggerAsBtn_SideL,
-        313 => ButtonName::LowerTriggerAsBtn_SideR,
-
-        310 => ButtonName::UpperTrigger_SideL,
-        311 => ButtonName::UpperTrigger_SideR,
-        //
-        314 => ButtonName::ExtraBtn_SideL,
-        315 => ButtonName::ExtraBtn_SideR,
-        316 => ButtonName::ExtraBtnCentral,
-        //
-        _ => bail!("Unknown button: {code}"),
-    })
-}
+// Analog triggers are reported as buttons with values from 0 to 1.
+// They go through the axis path to use 'triggers_threshold_pct'
+fn normalize_button(device_profile: &mut DeviceProfile, value: f32, code: u16) -> TransformStatus {
+    if let Some(axis) = device_profile.axes.get(&code).copied() {
+        return TransformStatus::Transformed(TransformedEvent {
+            event_type: EventTypeName::AxisChanged,
+            axis,
+            // Same range as the other gilrs axes
+            value: value * 2.0 - 1.0,
+            button: ButtonName::None,
+        });
+    }
 
-pub fn match_axis(code: u16) -> Result<AxisName> {
-    Ok(match code {
-        16 => AxisName::PadX_SideL,
-        17 => AxisName::PadY_SideL,
-        //
-        3 => AxisName::PadX_SideR,
-        4 => AxisName::PadY_SideR,
-        //
-        21 => AxisName::LowerTrigger_SideL,
-        20 => AxisName::LowerTrigger_SideR,
-        //
-        0 => AxisName::StickX,
-        1 => AxisName::StickY,
-        //
-        _ => bail!("Unknown button: {code}"),
+    let Some(button) = device_profile.match_button(code) else {
+        return TransformStatus::Discarded;
+    };
+    TransformStatus::Transformed(TransformedEvent {
+        event_type: match value >= BUTTON_PRESS_THRESHOLD {
+            true => EventTypeName::ButtonPressed,
+            false => EventTypeName::ButtonReleased,
+        },
+        axis: AxisName::None,
+        value,
+        button,
     })
 }
 
-pub fn normalize_event(event: &EventType, RESET_BTN: ButtonName) -> Result<TransformStatus> {
+pub fn normalize_event(
+    event: &EventType,
+    device_profile: &mut DeviceProfile,
+    RESET_BTN: ButtonName,
+) -> Result<TransformStatus> {
     Ok(match event {
         AxisChanged(axis, value, code) => {
             let code_as_num = print_code(code)?;
-            let axis = match_axis(code_as_num)?;
+            let Some(axis) = device_profile.match_axis(code_as_num) else {
+                return Ok(TransformStatus::Discarded);
+            };
             let value = *value;
 
             TransformStatus::Transformed(TransformedEvent {
@@ -272,26 +401,16 @@ pub fn normalize_event(event: &EventType, RESET_BTN: ButtonName) -> Result<Trans
                 axis,
                 //SUPER Important: Steam Controller's Left pad inverts Y axis and thus
                 // makes angles negative (angles go clockwise instead of counter-clockwise)
-                // need to invert it back
-                value: match axis {
-                    AxisName::PadY_SideL => -value,
-                    _ => value,
+                // need to invert it back. Device profile lists such axes
+                value: match device_profile.is_inverted(axis) {
+                    true => -value,
+                    false => value,
                 },
                 button: ButtonName::None,
             })
         }
-        ButtonChanged(button, value, code) => {
-            let code_as_num = print_code(code)?;
-            TransformStatus::Transformed(TransformedEvent {
-                event_type: match *value {
-                    0f32 => EventTypeName::ButtonReleased,
-                    1f32 => EventTypeName::ButtonPressed,
-                    _ => bail!("Cannot happen"),
-                },
-                axis: AxisName::None,
-                value: *value,
-                button: match_button(code_as_num)?,
-            })
+        ButtonChanged(_, value, code) => {
+            normalize_button(device_profile, *value, print_code(code)?)
         }
         Disconnected => TransformStatus::Transformed(TransformedEvent {
             event_type: EventTypeName::ButtonReleased,
@@ -400,3 +519,71 @@ pub fn print_event(event: &EventType) -> Result<String> {
     };
     Ok(format!("{event_type}; BtnOrAxis: {button_or_axis}; Value: {:.3}; Code: {code_as_str}; Num: {code_as_num}", res_value))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+
+    fn built_in_profile(name: &str) -> DeviceProfile {
+        // No devices folder
+        let devices_dir = temp_dir().join(format!("joystick-gilrs-devices-{}", std::process::id()));
+        DeviceProfiles::load(devices_dir)
+            .unwrap()
+            .get(name)
+            .unwrap()
+    }
+
+    fn transformed(status: TransformStatus) -> TransformedEvent {
+        match status {
+            TransformStatus::Transformed(event) => event,
+            _ => panic!("Event is not transformed"),
+        }
+    }
+
+    fn assert_trigger_axis(device_profile: &mut DeviceProfile, code: u16, axis: AxisName) {
+        for (value, expected) in [(0.0, -1.0), (0.25, -0.5), (1.0, 1.0)] {
+            let event = transformed(normalize_button(device_profile, value, code));
+            assert_eq!(event.event_type, EventTypeName::AxisChanged);
+            assert_eq!(event.axis, axis);
+            assert_eq!(event.button, ButtonName::None);
+            assert_eq!(event.value, expected);
+        }
+    }
+
+    #[test]
+    fn xbox_analog_trigger_goes_through_axis() {
+        let mut device_profile = built_in_profile("xbox");
+        assert_trigger_axis(&mut device_profile, 2, AxisName::LowerTrigger_SideL);
+        assert_trigger_axis(&mut device_profile, 5, AxisName::LowerTrigger_SideR);
+    }
+
+    #[test]
+    fn dualshock_analog_trigger_goes_through_axis() {
+        let mut device_profile = built_in_profile("dualshock");
+        assert_trigger_axis(&mut device_profile, 2, AxisName::LowerTrigger_SideL);
+        assert_trigger_axis(&mut device_profile, 5, AxisName::LowerTrigger_SideR);
+
+        // Digital L2/R2 codes stay buttons
+        let event = transformed(normalize_button(&mut device_profile, 1.0, 313));
+        assert_eq!(event.event_type, EventTypeName::ButtonPressed);
+        assert_eq!(event.button, ButtonName::LowerTriggerAsBtn_SideR);
+        assert_eq!(event.axis, AxisName::None);
+    }
+
+    #[test]
+    fn button_value_is_compared_with_threshold() {
+        let mut device_profile = built_in_profile("xbox");
+
+        let event = transformed(normalize_button(&mut device_profile, 0.6, 304));
+        assert_eq!(event.event_type, EventTypeName::ButtonPressed);
+        assert_eq!(event.button, ButtonName::BtnDown_SideR);
+        let event = transformed(normalize_button(&mut device_profile, 0.4, 304));
+        assert_eq!(event.event_type, EventTypeName::ButtonReleased);
+
+        assert!(matches!(
+            normalize_button(&mut device_profile, 1.0, 999),
+            TransformStatus::Discarded
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+use crate::configs::{InputBackendName, MainConfigs};
+use crate::gilrs_specific::GilrsBackend;
+use crate::steamy_specific::SteamyBackend;
+use crate::utils::TerminationStatus;
+use color_eyre::eyre::{bail, Result};
+
+pub trait InputBackend {
+    fn name(&self) -> InputBackendName;
+
+    /// Checks if a controller for this backend is connected
+    fn is_available(&mut self) -> bool;
+
+    /// Blocks until termination. Spawns writing threads on its own
+    fn run(&mut self, configs: &MainConfigs, termination_status: &TerminationStatus) -> Result<()>;
+}
+
+pub fn select_backend(backend_name: InputBackendName) -> Result<Box<dyn InputBackend>> {
+    Ok(match backend_name {
+        InputBackendName::Steamy => Box::new(SteamyBackend::default()),
+        InputBackendName::Gilrs => Box::new(GilrsBackend),
+        InputBackendName::Auto => {
+            let mut steamy_backend = SteamyBackend::default();
+            if steamy_backend.is_available() {
+                Box::new(steamy_backend)
+            } else {
+                println!("Steam Controller is not connected. Using gilrs");
+                Box::new(GilrsBackend)
+            }
+        }
+        InputBackendName::Replay => bail!("Replay backend requires '--replay'"),
+    })
+}
@@ -1,7 +1,245 @@
 use crate::match_event::ButtonName;
+use crate::utils::edit_distance;
+use ahash::AHashMap;
 use color_eyre::eyre::{bail, Report, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use strum::IntoEnumIterator;
 use universal_input::{KeyCode, KeyCodes};
 
+const NEXT_LAYOUT: &str = "NEXT_LAYOUT";
+const PREV_LAYOUT: &str = "PREV_LAYOUT";
+const LAYOUT_PREFIX: &str = "LAYOUT(";
+const LAYER_PREFIX: &str = "LAYER(";
+
+// Special codes that switch the buttons layout at runtime
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub enum LayoutSwitch {
+    // Steps through the playlist from configs.yaml
+    Next,
+    Previous,
+    Named(String),
+}
+
+// Name inside 'PREFIX(name)'
+fn name_from_config(code_str: &str, prefix: &str) -> Result<Option<String>> {
+    let Some(name) = code_str.strip_prefix(prefix) else {
+        return Ok(None);
+    };
+    let Some(name) = name.strip_suffix(")") else {
+        bail!("Missing ')' in '{}'", code_str)
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        bail!("Name is not specified in '{}'", code_str)
+    }
+    Ok(Some(name.to_string()))
+}
+
+fn layout_switch_from_config(code_str: &str) -> Result<Option<LayoutSwitch>> {
+    if code_str == NEXT_LAYOUT {
+        return Ok(Some(LayoutSwitch::Next));
+    }
+    if code_str == PREV_LAYOUT {
+        return Ok(Some(LayoutSwitch::Previous));
+    }
+    Ok(name_from_config(code_str, LAYOUT_PREFIX)?.map(LayoutSwitch::Named))
+}
+
+// Keeps the YAML path of the code to find its position in the file
+#[derive(Clone, Debug)]
+pub struct KeyCodeError {
+    pub yaml_path: Vec<String>,
+    pub code_str: String,
+    pub suggestion: Option<String>,
+}
+
+impl KeyCodeError {
+    fn new(yaml_path: &str, code_str: &str) -> Self {
+        Self {
+            yaml_path: yaml_path.split('.').map(str::to_string).collect(),
+            code_str: code_str.to_string(),
+            suggestion: suggest_key_code(code_str),
+        }
+    }
+}
+
+impl fmt::Display for KeyCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}': unknown key code '{}'",
+            self.yaml_path.join("."),
+            self.code_str
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ". Did you mean '{}'?", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for KeyCodeError {}
+
+// Every code accepted in layouts except 'LAYOUT(name)' and 'LAYER(name)'
+pub fn key_code_names() -> Vec<String> {
+    let mut names: Vec<String> = KeyCode::iter()
+        .map(|key_code| key_code.to_string())
+        .collect();
+    names.extend([NEXT_LAYOUT.to_string(), PREV_LAYOUT.to_string()]);
+    names
+}
+
+pub fn named_code_pattern() -> String {
+    format!(
+        "^({}|{}).+\\)$",
+        regex::escape(LAYOUT_PREFIX),
+        regex::escape(LAYER_PREFIX)
+    )
+}
+
+pub fn suggest_key_code(code_str: &str) -> Option<String> {
+    let code_str = code_str.to_uppercase();
+    let (distance, name) = key_code_names()
+        .into_iter()
+        .map(|name| (edit_distance(code_str.as_str(), name.as_str()), name))
+        .min()?;
+    // Too different names are not helpful
+    match distance <= (code_str.len() / 3).max(2) {
+        true => Some(name),
+        false => None,
+    }
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim_start();
+    line.is_empty() || line.starts_with('#')
+}
+
+// Key with the list index, e.g. 'chords[2]'
+fn parse_path_step(step: &str) -> (&str, Option<usize>) {
+    let index = step
+        .strip_suffix(']')
+        .and_then(|step| step.split_once('['))
+        .and_then(|(key, index)| Some((key, index.parse().ok()?)));
+    match index {
+        Some((key, index)) => (key, Some(index)),
+        None => (step, None),
+    }
+}
+
+// Column of 'key:' at the start of the line. Dashes of list items are skipped
+fn key_column(line: &str, key: &str) -> Option<usize> {
+    let mut rest = line.trim_start();
+    while let Some(item) = rest.strip_prefix('-') {
+        rest = item.trim_start();
+    }
+    rest.strip_prefix(key)?
+        .starts_with(':')
+        .then_some(line.len() - rest.len())
+}
+
+// First line after 'line_ind' that is not nested deeper than 'block_indent'.
+// List items of a key can have the same indent as the key
+fn block_end(lines: &[&str], line_ind: usize, block_indent: usize, allow_items: bool) -> usize {
+    (line_ind + 1..lines.len())
+        .find(|&ind| {
+            let line = lines[ind];
+            if is_blank_or_comment(line) {
+                return false;
+            }
+            let is_item = allow_items && line.trim_start().starts_with('-');
+            indent(line) < block_indent || (indent(line) == block_indent && !is_item)
+        })
+        .unwrap_or(lines.len())
+}
+
+// Line and column (both from 1) of the code inside the nested keys.
+// Every key is searched only inside the block of its parent
+fn find_code_position(content: &str, error: &KeyCodeError) -> Option<(usize, usize)> {
+    let lines: Vec<&str> = content.lines().collect();
+
+    // Lines of the current block. Top-level keys aren't indented
+    let (mut start, mut end) = (0, lines.len());
+    let mut parent_column = None;
+    // Keys of flow mappings like '{ tap: [...] }' are on the line of their parent
+    let mut inline_column = 0;
+
+    for step in &error.yaml_path {
+        let (key, index) = parse_path_step(step.as_str());
+
+        let found = (start..end).find_map(|ind| {
+            let column = key_column(lines[ind], key)?;
+            let is_child = match parent_column {
+                None => column == 0,
+                Some(parent_column) => column > parent_column,
+            };
+            is_child.then_some((ind, column))
+        });
+        let (key_ind, column) = match found {
+            Some(found) => found,
+            None => {
+                let inline_key = format!("{}:", key);
+                let line = lines.get(start)?;
+                inline_column += line[inline_column..].find(inline_key.as_str())?;
+                continue;
+            }
+        };
+        start = key_ind;
+        end = block_end(&lines, key_ind, column, true);
+        parent_column = Some(column);
+        inline_column = 0;
+
+        if let Some(index) = index {
+            let items: Vec<usize> = (start + 1..end)
+                .filter(|&ind| lines[ind].trim_start().starts_with('-'))
+                .collect();
+            let items_indent = indent(lines[*items.first()?]);
+            let item_ind = items
+                .into_iter()
+                .filter(|&ind| indent(lines[ind]) == items_indent)
+                .nth(index)?;
+            start = item_ind;
+            end = block_end(&lines, item_ind, items_indent, false);
+            parent_column = Some(items_indent);
+        }
+    }
+
+    for ind in start..end {
+        let from = match ind == start {
+            true => inline_column,
+            false => 0,
+        };
+        if let Some(column) = lines[ind][from..].find(error.code_str.as_str()) {
+            return Some((ind + 1, from + column + 1));
+        }
+    }
+    None
+}
+
+// Adds the file position to errors about key codes.
+// Files are searched in order, so the most specific one has to be first
+pub fn locate_key_code_error(err: Report, filepaths: &[PathBuf]) -> Report {
+    let location = err.downcast_ref::<KeyCodeError>().and_then(|error| {
+        filepaths.iter().find_map(|filepath| {
+            let content = read_to_string(filepath).ok()?;
+            let (line, column) = find_code_position(content.as_str(), error)?;
+            Some(format!("{}:{}:{}", filepath.display(), line, column))
+        })
+    });
+    match (location, filepaths.first()) {
+        (Some(location), _) => err.wrap_err(location),
+        (None, Some(filepath)) => err.wrap_err(format!("{}", filepath.display())),
+        (None, None) => err,
+    }
+}
+
 fn assign_special_button(special_button: &mut ButtonName, value: ButtonName) -> Result<KeyCode> {
     if *special_button != ButtonName::DefaultForSpecialBtns {
         bail!(
@@ -16,19 +254,46 @@ fn assign_special_button(special_button: &mut ButtonName, value: ButtonName) ->
 }
 
 pub fn key_code_from_config(
+    section: &str,
     button_name: ButtonName,
     code_str: String,
     reset_btn: &mut ButtonName,
     switch_mode_btn: &mut ButtonName,
+    layout_switches: &mut AHashMap<ButtonName, LayoutSwitch>,
+    layer_switches: &mut AHashMap<ButtonName, String>,
     detect_special: bool,
 ) -> Result<KeyCode> {
     if code_str == "" {
         return Ok(KeyCode::None);
     };
 
+    if let Some(layout_switch) = layout_switch_from_config(code_str.as_str())
+        .map_err(|err| err.wrap_err(format!("'{button_name}'")))?
+    {
+        if !detect_special {
+            bail!("'{button_name}': '{code_str}' cannot be combined with other codes")
+        }
+        layout_switches.insert(button_name, layout_switch);
+        return Ok(KeyCode::None);
+    }
+
+    if let Some(layer_name) = name_from_config(code_str.as_str(), LAYER_PREFIX)
+        .map_err(|err| err.wrap_err(format!("'{button_name}'")))?
+    {
+        if !detect_special {
+            bail!("'{button_name}': '{code_str}' cannot be combined with other codes")
+        }
+        layer_switches.insert(button_name, layer_name);
+        return Ok(KeyCode::None);
+    }
+
     let key_code = KeyCode::try_from(code_str.as_str());
     match key_code {
-        Err(err) => Err(Report::new(err).wrap_err(format!("'{button_name}'"))),
+        Err(_) => Err(KeyCodeError::new(
+            format!("{section}.{button_name}").as_str(),
+            code_str.as_str(),
+        )
+        .into()),
         Ok(key_code) => {
             if detect_special {
                 match key_code {
@@ -45,3 +310,175 @@ pub fn key_code_from_config(
         }
     }
 }
+
+pub fn key_codes_from_config<S: AsRef<str>>(context: &str, codes: &[S]) -> Result<KeyCodes> {
+    let mut key_codes = KeyCodes::new();
+    for code_str in codes {
+        let code_str = code_str.as_ref();
+        if code_str == "" {
+            continue;
+        }
+        match KeyCode::try_from(code_str) {
+            Err(_) => return Err(KeyCodeError::new(context, code_str).into()),
+            Ok(key_code) => key_codes.push(key_code),
+        }
+    }
+    Ok(key_codes)
+}
+
+// US layout. Returns the key name and whether Shift is needed
+fn key_name_from_char(char: char) -> Option<(String, bool)> {
+    if char.is_ascii_alphanumeric() {
+        return Some((
+            char.to_ascii_uppercase().to_string(),
+            char.is_ascii_uppercase(),
+        ));
+    }
+    let (name, shift) = match char {
+        ' ' => ("SPACE", false),
+        '\n' => ("ENTER", false),
+        '\t' => ("TAB", false),
+        '.' => ("DOT", false),
+        ',' => ("COMMA", false),
+        '\'' => ("APOSTROPHE", false),
+        '-' => ("MINUS", false),
+        '=' => ("EQUAL", false),
+        '/' => ("SLASH", false),
+        ';' => ("SEMICOLON", false),
+        '[' => ("LEFTBRACE", false),
+        ']' => ("RIGHTBRACE", false),
+        '\\' => ("BACKSLASH", false),
+        '`' => ("GRAVE", false),
+        '!' => ("1", true),
+        '@' => ("2", true),
+        '#' => ("3", true),
+        '$' => ("4", true),
+        '%' => ("5", true),
+        '^' => ("6", true),
+        '&' => ("7", true),
+        '*' => ("8", true),
+        '(' => ("9", true),
+        ')' => ("0", true),
+        '>' => ("DOT", true),
+        '<' => ("COMMA", true),
+        '"' => ("APOSTROPHE", true),
+        '_' => ("MINUS", true),
+        '+' => ("EQUAL", true),
+        '?' => ("SLASH", true),
+        ':' => ("SEMICOLON", true),
+        '{' => ("LEFTBRACE", true),
+        '}' => ("RIGHTBRACE", true),
+        '|' => ("BACKSLASH", true),
+        '~' => ("GRAVE", true),
+        _ => return None,
+    };
+    Some((name.to_string(), shift))
+}
+
+/// Key strokes typing the text, one per character
+pub fn key_strokes_from_text(text: &str) -> Result<Vec<KeyCodes>> {
+    let mut key_strokes = vec![];
+    for char in text.chars() {
+        let Some((key_name, shift)) = key_name_from_char(char) else {
+            bail!("Character '{}' of '{}' can't be typed", char, text)
+        };
+        let Ok(key_code) = KeyCode::try_from(format!("KEY_{key_name}").as_str()) else {
+            bail!("Character '{}' of '{}' can't be typed", char, text)
+        };
+        key_strokes.push(match shift {
+            true => vec![KeyCode::KEY_LEFTSHIFT, key_code],
+            false => vec![key_code],
+        });
+    }
+    Ok(key_strokes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(content: &str, yaml_path: &str, code_str: &str) -> Option<(usize, usize)> {
+        find_code_position(content, &KeyCodeError::new(yaml_path, code_str))
+    }
+
+    #[test]
+    fn code_position_in_nested_keys() {
+        let content = "\
+ButtonsLayout:
+  Wing_SideL: [ \"KEY_TABB\" ]
+Layers:
+  hotbar:
+    Wing_SideL: [ \"KEY_TABB\" ]
+";
+        assert_eq!(
+            position(content, "ButtonsLayout.Wing_SideL", "KEY_TABB"),
+            Some((2, 18))
+        );
+        assert_eq!(
+            position(content, "Layers.hotbar.Wing_SideL", "KEY_TABB"),
+            Some((5, 20))
+        );
+    }
+
+    #[test]
+    fn code_position_stays_inside_parent_block() {
+        let content = "\
+Layers:
+  hotbar:
+    BtnUp_SideR: [ \"KEY_1\" ]
+  other:
+    Wing_SideL: [ \"KEY_TABB\" ]
+";
+        assert_eq!(
+            position(content, "Layers.hotbar.Wing_SideL", "KEY_TABB"),
+            None
+        );
+    }
+
+    #[test]
+    fn code_position_in_list_items() {
+        let content = "\
+chords:
+  - buttons: [ \"BtnDown_SideR\" ]
+    keys: [ \"KEY_SPACEE\" ]
+  - buttons: [ \"BtnUp_SideR\" ]
+    keys: [ \"KEY_SPACEE\" ]
+";
+        assert_eq!(
+            position(content, "chords[0].keys", "KEY_SPACEE"),
+            Some((3, 14))
+        );
+        assert_eq!(
+            position(content, "chords[1].keys", "KEY_SPACEE"),
+            Some((5, 14))
+        );
+        assert_eq!(position(content, "chords[2].keys", "KEY_SPACEE"), None);
+    }
+
+    #[test]
+    fn code_position_in_flow_mapping() {
+        let content = "\
+ButtonsLayout:
+  Wing_SideL: { tap: [ \"KEY_TABB\" ], hold: [ \"KEY_TABB\" ] }
+";
+        assert_eq!(
+            position(content, "ButtonsLayout.Wing_SideL.tap", "KEY_TABB"),
+            Some((2, 25))
+        );
+        assert_eq!(
+            position(content, "ButtonsLayout.Wing_SideL.hold", "KEY_TABB"),
+            Some((2, 47))
+        );
+    }
+
+    #[test]
+    fn suggestions_for_misspelled_codes() {
+        assert_eq!(suggest_key_code("KEY_ENTR").as_deref(), Some("KEY_ENTER"));
+        assert_eq!(suggest_key_code("key_space").as_deref(), Some("KEY_SPACE"));
+        assert_eq!(
+            suggest_key_code("NEXT_LAYOT").as_deref(),
+            Some("NEXT_LAYOUT")
+        );
+        assert_eq!(suggest_key_code("SOMETHING_COMPLETELY_DIFFERENT"), None);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::layout_inheritance::EXTENDS_KEY;
+use crate::match_event::ButtonName;
+use color_eyre::eyre::Result;
+use serde_yml::{Mapping, Value};
+
+// Directions in order: up, right, down, left
+const WASD_CODES: [&str; 4] = ["KEY_W", "KEY_D", "KEY_S", "KEY_A"];
+
+// Left pad presses WASD by zones in gaming mode
+pub fn is_wasd(dpad_codes: &[Vec<String>; 4]) -> bool {
+    dpad_codes
+        .iter()
+        .zip(WASD_CODES)
+        .all(|(codes, code)| *codes == [code])
+}
+
+// Layout converted from configs of other tools
+#[derive(Clone, Debug, Default)]
+pub struct ImportedLayout {
+    pub source: String,
+    pub title: Option<String>,
+    pub buttons: Vec<(ButtonName, Vec<String>)>,
+    // Not changed if the config doesn't tell
+    pub gaming_mode: Option<bool>,
+    // Everything that has no equivalent here
+    pub skipped: Vec<String>,
+}
+
+impl ImportedLayout {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn skip(&mut self, item: String) {
+        self.skipped.push(item);
+    }
+
+    pub fn to_layout_yaml(&self, base_layout: &str) -> Result<String> {
+        let mut layout = Mapping::new();
+        layout.insert(EXTENDS_KEY.into(), base_layout.into());
+
+        if let Some(gaming_mode) = self.gaming_mode {
+            let mut general = Mapping::new();
+            general.insert("gaming_mode".into(), gaming_mode.into());
+            layout.insert("General".into(), Value::Mapping(general));
+        }
+        if self.gaming_mode == Some(true) {
+            let mut wasd = Mapping::new();
+            wasd.insert("diagonal_zones".into(), true.into());
+            wasd.insert("zone_range".into(), 22.into());
+            wasd.insert("start_threshold_pct".into(), 30.into());
+            wasd.insert("shift_threshold_pct".into(), 80.into());
+            layout.insert("WASD".into(), Value::Mapping(wasd));
+        }
+
+        let mut buttons_layout = Mapping::new();
+        for (button, codes) in &self.buttons {
+            let codes = codes.iter().map(|code| code.as_str().into()).collect();
+            buttons_layout.insert(button.to_string().into(), Value::Sequence(codes));
+        }
+        layout.insert("ButtonsLayout".into(), Value::Mapping(buttons_layout));
+
+        let mut content = match &self.title {
+            Some(title) => format!("# Imported from {} '{}'\n", self.source, title),
+            None => format!("# Imported from {}\n", self.source),
+        };
+        content += serde_yml::to_string(&Value::Mapping(layout))?.as_str();
+        Ok(content)
+    }
+}
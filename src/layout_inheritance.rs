@@ -0,0 +1,268 @@
+use crate::file_ops::{read_yaml, yaml_filepath};
+use color_eyre::eyre::{bail, Result, WrapErr};
+use serde_yml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+pub const EXTENDS_KEY: &str = "extends";
+pub const INCLUDE_KEY: &str = "include";
+
+// Button bindings are replaced as a whole, so '{ tap: X }' doesn't keep 'hold' of the base
+fn is_binding(path: &[String]) -> bool {
+    match path {
+        [section, _] => section == "ButtonsLayout",
+        [section, _, _] => section == "Layers",
+        _ => false,
+    }
+}
+
+fn merge_at(base: &mut Value, overlay: Value, path: &mut Vec<String>) {
+    if is_binding(path) {
+        *base = overlay;
+        return;
+    }
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => {
+                        path.push(key.as_str().unwrap_or_default().to_string());
+                        merge_at(base_value, value, path);
+                        path.pop();
+                    }
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// Sections are merged key by key, so 'ButtonsLayout' is merged per button.
+// Other values are replaced. Null value disables the section of the base layout
+pub fn merge_yaml(base: &mut Value, overlay: Value) {
+    merge_at(base, overlay, &mut vec![]);
+}
+
+fn take_layout_names(mapping: &mut Mapping, key: &str) -> Result<Vec<String>> {
+    match mapping.remove(key) {
+        None | Some(Value::Null) => Ok(vec![]),
+        Some(Value::String(name)) => Ok(vec![name]),
+        Some(Value::Sequence(names)) => names
+            .into_iter()
+            .map(|name| match name {
+                Value::String(name) => Ok(name),
+                _ => bail!("'{}' has to contain layout names", key),
+            })
+            .collect(),
+        Some(_) => bail!("'{}' has to be a layout name or a list of them", key),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ResolvedLayout {
+    pub value: Value,
+    // Files the layout consists of. Most specific first
+    pub filepaths: Vec<PathBuf>,
+}
+
+impl ResolvedLayout {
+    pub fn is_single_file(&self) -> bool {
+        self.filepaths.len() == 1
+    }
+}
+
+fn resolve(
+    layouts_dir: &Path,
+    layout_name: &str,
+    chain: &mut Vec<String>,
+    filepaths: &mut Vec<PathBuf>,
+) -> Result<Value> {
+    if chain.iter().any(|name| name == layout_name) {
+        bail!(
+            "Layout inheritance cycle: {} -> {}",
+            chain.join(" -> "),
+            layout_name
+        )
+    }
+    chain.push(layout_name.to_string());
+
+    let mut value: Value = read_yaml(layouts_dir, layout_name)?;
+    let Value::Mapping(ref mut mapping) = value else {
+        bail!("Layout '{}' has to be a mapping", layout_name)
+    };
+    let base_names = take_layout_names(mapping, EXTENDS_KEY)?;
+    let include_names = take_layout_names(mapping, INCLUDE_KEY)?;
+    if base_names.len() > 1 {
+        bail!("Layout '{}' can extend only one layout", layout_name)
+    }
+    filepaths.push(yaml_filepath(layouts_dir, layout_name));
+
+    // Later includes override earlier ones, so they are searched first
+    let mut include_values = vec![];
+    for include_name in include_names.iter().rev() {
+        let include_value = resolve(layouts_dir, include_name, chain, filepaths)
+            .wrap_err(format!("Included from '{}'", layout_name))?;
+        include_values.push(include_value);
+    }
+
+    let mut merged = match base_names.first() {
+        None => Value::Mapping(Mapping::new()),
+        Some(base_name) => resolve(layouts_dir, base_name, chain, filepaths)
+            .wrap_err(format!("Extended by '{}'", layout_name))?,
+    };
+    for include_value in include_values.into_iter().rev() {
+        merge_yaml(&mut merged, include_value);
+    }
+    merge_yaml(&mut merged, value);
+
+    chain.pop();
+    // Only the requested layout keeps its keys, so they can be shown
+    if chain.is_empty() {
+        if let (Value::Mapping(merged), Some(base_name)) = (&mut merged, base_names.first()) {
+            merged.insert(EXTENDS_KEY.into(), base_name.as_str().into());
+        }
+        if let Value::Mapping(merged) = &mut merged {
+            let include_names = include_names.into_iter().map(Value::String).collect();
+            merged.insert(INCLUDE_KEY.into(), Value::Sequence(include_names));
+        }
+    }
+    Ok(merged)
+}
+
+// Order: base layout, then includes, then the layout itself
+pub fn resolve_layout(layouts_dir: &Path, layout_name: &str) -> Result<ResolvedLayout> {
+    let mut filepaths = vec![];
+    let value = resolve(layouts_dir, layout_name, &mut vec![], &mut filepaths)?;
+    Ok(ResolvedLayout { value, filepaths })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    fn yaml(content: &str) -> Value {
+        serde_yml::from_str(content).unwrap()
+    }
+
+    fn merged(base: &str, overlay: &str) -> Value {
+        let mut base = yaml(base);
+        merge_yaml(&mut base, yaml(overlay));
+        base
+    }
+
+    #[test]
+    fn overlay_overrides_values() {
+        assert_eq!(
+            merged(
+                "General: { gaming_mode: false, mouse_speed: 400 }",
+                "General: { gaming_mode: true }",
+            ),
+            yaml("General: { gaming_mode: true, mouse_speed: 400 }")
+        );
+        assert_eq!(
+            merged("Gyro: { use_gyro: true }", "Gyro: null"),
+            yaml("Gyro: null")
+        );
+    }
+
+    #[test]
+    fn nested_maps_are_merged_key_by_key() {
+        assert_eq!(
+            merged(
+                "Tilt: { use_tilt: true, zones: { zone_range: 30, diagonal_zones: false } }",
+                "Tilt: { zones: { zone_range: 45 } }",
+            ),
+            yaml("Tilt: { use_tilt: true, zones: { zone_range: 45, diagonal_zones: false } }")
+        );
+        assert_eq!(
+            merged(
+                "ButtonsLayout: { BtnUp_SideR: [ KEY_UP ], Wing_SideL: [ KEY_TAB ] }",
+                "ButtonsLayout: { Wing_SideL: [ KEY_LEFTSHIFT ] }",
+            ),
+            yaml("ButtonsLayout: { BtnUp_SideR: [ KEY_UP ], Wing_SideL: [ KEY_LEFTSHIFT ] }")
+        );
+    }
+
+    #[test]
+    fn bindings_are_replaced_as_a_whole() {
+        assert_eq!(
+            merged(
+                "ButtonsLayout: { Wing_SideL: { tap: [ KEY_TAB ], hold: [ KEY_LEFTSHIFT ] } }",
+                "ButtonsLayout: { Wing_SideL: { tap: [ KEY_Q ] } }",
+            ),
+            yaml("ButtonsLayout: { Wing_SideL: { tap: [ KEY_Q ] } }")
+        );
+        assert_eq!(
+            merged(
+                "Layers: { hotbar: { BtnUp_SideR: { tap: [ KEY_1 ], hold: [ KEY_2 ] } } }",
+                "Layers: { hotbar: { BtnUp_SideR: { tap: [ KEY_3 ] } } }",
+            ),
+            yaml("Layers: { hotbar: { BtnUp_SideR: { tap: [ KEY_3 ] } } }")
+        );
+    }
+
+    fn write_layouts(test_name: &str, layouts: &[(&str, &str)]) -> PathBuf {
+        let layouts_dir = temp_dir().join(format!(
+            "joystick-layout-inheritance-{}-{}",
+            test_name,
+            std::process::id()
+        ));
+        create_dir_all(&layouts_dir).unwrap();
+        for (layout_name, content) in layouts {
+            write(yaml_filepath(&layouts_dir, layout_name), content).unwrap();
+        }
+        layouts_dir
+    }
+
+    #[test]
+    fn extends_and_includes_are_merged_in_order() {
+        let layouts_dir = write_layouts(
+            "order",
+            &[
+                ("base", "General: { mouse_speed: 400, gaming_mode: false }"),
+                ("fast", "General: { mouse_speed: 800 }"),
+                (
+                    "child",
+                    "extends: base\ninclude: [ fast ]\nGeneral: { gaming_mode: true }",
+                ),
+            ],
+        );
+        let resolved = resolve_layout(&layouts_dir, "child").unwrap();
+        remove_dir_all(&layouts_dir).unwrap();
+
+        assert_eq!(
+            resolved.value,
+            yaml(
+                "General: { mouse_speed: 800, gaming_mode: true }\nextends: base\ninclude: [ fast ]"
+            )
+        );
+        assert_eq!(resolved.filepaths.len(), 3);
+        assert!(!resolved.is_single_file());
+    }
+
+    #[test]
+    fn inheritance_cycle_is_an_error() {
+        let layouts_dir = write_layouts(
+            "cycle",
+            &[
+                ("first", "extends: second"),
+                ("second", "include: [ third ]"),
+                ("third", "extends: first"),
+            ],
+        );
+        let result = resolve_layout(&layouts_dir, "first");
+        remove_dir_all(&layouts_dir).unwrap();
+
+        let err = result.unwrap_err();
+        assert!(
+            format!("{:?}", err)
+                .contains("Layout inheritance cycle: first -> second -> third -> first"),
+            "{:?}",
+            err
+        );
+    }
+}
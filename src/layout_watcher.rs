@@ -0,0 +1,106 @@
+use crate::configs::LayoutConfigs;
+use crate::output_sink::RecordingSink;
+use crate::writing_thread::EventWriter;
+use color_eyre::eyre::Result;
+use log::warn;
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+type ModifiedTimes = Vec<(PathBuf, SystemTime)>;
+
+// Included layouts can be kept in subfolders
+fn scan_dir(dir: &Path, modified_times: &mut ModifiedTimes) -> Result<()> {
+    for entry in read_dir(dir)? {
+        let filepath = entry?.path();
+        if filepath.is_dir() {
+            scan_dir(filepath.as_path(), modified_times)?;
+        } else if filepath.extension().is_some_and(|ext| ext == "yaml") {
+            let modified = filepath.metadata()?.modified()?;
+            modified_times.push((filepath, modified));
+        }
+    }
+    Ok(())
+}
+
+// Polls modification times of the layout files.
+// Whole folder is watched since layouts can depend on each other
+#[derive(Clone, Debug)]
+pub struct LayoutWatcher {
+    layouts_dir: PathBuf,
+    layout_name: String,
+    modified_times: ModifiedTimes,
+    last_check: Instant,
+}
+
+impl LayoutWatcher {
+    pub fn new(layouts_dir: PathBuf, layout_name: &str) -> Self {
+        let mut watcher = Self {
+            layouts_dir,
+            layout_name: layout_name.to_string(),
+            modified_times: vec![],
+            last_check: Instant::now(),
+        };
+        watcher.modified_times = watcher.scan().unwrap_or_default();
+        watcher
+    }
+
+    fn scan(&self) -> Result<ModifiedTimes> {
+        let mut modified_times: ModifiedTimes = vec![];
+        scan_dir(self.layouts_dir.as_path(), &mut modified_times)?;
+        modified_times.sort();
+        Ok(modified_times)
+    }
+
+    pub fn layout_name(&self) -> &str {
+        self.layout_name.as_str()
+    }
+
+    fn load(&self, layout_name: &str) -> Result<LayoutConfigs> {
+        let layout_configs = LayoutConfigs::load(layout_name, self.layouts_dir.as_path())?;
+        // Writing thread must be able to use it as well
+        EventWriter::from_layout(&layout_configs, false, RecordingSink::new())?;
+        Ok(layout_configs)
+    }
+
+    // Starts watching another layout if it's valid
+    pub fn switch_to(&mut self, layout_name: &str) -> Result<LayoutConfigs> {
+        let layout_configs = self.load(layout_name)?;
+        self.layout_name = layout_name.to_string();
+        self.modified_times = self.scan().unwrap_or_default();
+        Ok(layout_configs)
+    }
+
+    // Returns the layout only if it was changed and is valid
+    pub fn check(&mut self) -> Option<LayoutConfigs> {
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return None;
+        }
+        self.last_check = Instant::now();
+
+        let modified_times = match self.scan() {
+            Ok(modified_times) => modified_times,
+            Err(err) => {
+                warn!("Cannot check layouts for changes: {}", err);
+                return None;
+            }
+        };
+        if modified_times == self.modified_times {
+            return None;
+        }
+        self.modified_times = modified_times;
+
+        match self.load(self.layout_name.as_str()) {
+            Ok(layout_configs) => Some(layout_configs),
+            Err(err) => {
+                warn!(
+                    "Layout '{}' is not reloaded. Keeping the previous one: {}",
+                    self.layout_name, err
+                );
+                None
+            }
+        }
+    }
+}
@@ -1,29 +1,53 @@
 // #![feature(const_try)]
 
 mod buttons_state;
+mod chords;
+mod cli;
+mod commands;
+mod config_discovery;
+mod config_schema;
 mod configs;
+mod device_profile;
 mod file_ops;
 mod gilrs_specific;
+mod input_backend;
 mod key_codes;
+mod layout_import;
+mod layout_inheritance;
+mod layout_watcher;
 mod match_event;
 mod math_ops;
+mod motion_gestures;
+mod motion_sensor;
+mod output_sink;
 mod pads_ops;
 mod process_event;
+mod process_watcher;
+mod recorder;
+mod replay_specific;
+mod scc_import;
 mod steamy_debug;
 mod steamy_event;
 mod steamy_specific;
 mod steamy_state;
+mod typing;
 mod utils;
+mod vdf;
+mod vdf_import;
 mod writing_thread;
 
+use crate::cli::{Cli, Command};
+use crate::config_discovery::find_configs_dir;
+use crate::config_schema::write_schemas;
 use crate::configs::MainConfigs;
-use crate::process_event::{SharedInfo, process_event};
-use crate::utils::{TerminationStatus, ThreadHandle};
-use crate::writing_thread::write_events;
+use crate::scc_import::import_sccprofile;
+use crate::vdf_import::import_vdf;
+use clap::Parser;
 use color_eyre::eyre::Result;
 use env_logger::builder;
 use log::debug;
-use std::{env, thread};
+use std::env;
+use std::path::Path;
 
 fn init_logger() {
     if env::var("RUST_LOG").is_err() {
@@ -48,80 +72,57 @@ fn init_logger() {
         .init();
 }
 
-fn load_configs() -> Result<(SharedInfo, MainConfigs)> {
-    let mut configs = MainConfigs::load()?;
+fn load_configs(explicit_dir: Option<&Path>, command: &Option<Command>) -> Result<MainConfigs> {
+    let configs_dir = find_configs_dir(explicit_dir)?;
 
-    debug!("Layout: {}", configs.layout_names_cfg.buttons_layout_name);
+    // Other commands load the layouts they need by themselves
+    let configs = match command {
+        None | Some(Command::Run(_)) | Some(Command::DumpEvents { .. }) => {
+            MainConfigs::load(configs_dir)?
+        }
+        Some(_) => MainConfigs::load_main(configs_dir)?,
+    };
 
-    let shared_info = SharedInfo::new(&configs);
-    Ok((shared_info, configs))
-}
+    debug!("Layout: {}", configs.layout_names_cfg.buttons_layout_name);
 
-fn run_read_loop(
-    shared_info: &SharedInfo,
-    configs: &MainConfigs,
-    termination_status: &TerminationStatus,
-) -> Result<()> {
-    #[cfg(feature = "use_steamy")]
-    {
-        use crate::steamy_specific::run_steamy_loop;
-        crate::steamy_specific::run_steamy_loop(shared_info, configs, termination_status)?;
-    }
-    #[cfg(not(feature = "use_steamy"))]
-    {
-        use crate::gilrs_specific::run_gilrs_loop;
-        run_gilrs_loop(shared_info, configs, termination_status)?;
-    }
-    Ok(())
+    Ok(configs)
 }
 
 fn init_controller() -> Result<()> {
-    println!("App started");
+    let cli = Cli::parse();
 
     init_logger();
 
-    let (mut shared_info, configs) = load_configs()?;
-
-    let termination_status = TerminationStatus::default();
-
-    let termination_status_copy = termination_status.clone();
-    let mouse_receiver = shared_info.mouse_receiver.clone();
-    let button_receiver = shared_info.button_receiver.clone();
-    let configs_copy = configs.clone();
-
-    #[cfg(not(feature = "main_as_thread"))]
-    {
-        thread::spawn(move || {
-            termination_status_copy.check_result(write_events(
-                &mouse_receiver,
-                &button_receiver,
-                &configs_copy,
-                &termination_status_copy,
-            ));
-        });
+    let configs = load_configs(cli.config.as_deref(), &cli.command)?;
 
-        termination_status.check_result(run_read_loop(&shared_info, &configs, &termination_status));
-    };
-
-    #[cfg(feature = "main_as_thread")]
-    {
-        thread::spawn(move || {
-            termination_status_copy.check_result(run_read_loop(
-                &shared_info,
-                &configs_copy,
-                &termination_status_copy,
-            ));
-        });
-
-        termination_status.check_result(write_events(
-            &mouse_receiver,
-            &button_receiver,
+    match cli.command {
+        None => {
+            println!("App started");
+            commands::run(cli.run, configs)
+        }
+        Some(Command::Run(run_args)) => {
+            println!("App started");
+            commands::run(run_args, configs)
+        }
+        Some(Command::Validate { layout }) => commands::validate(layout.as_str(), &configs),
+        Some(Command::ListLayouts) => commands::list_layouts(&configs),
+        Some(Command::ShowZones { layout }) => commands::show_zones(layout.as_str(), &configs),
+        Some(Command::DumpEvents { backend }) => commands::dump_events(backend, configs),
+        Some(Command::Schema { output_dir }) => {
+            let output_dir = output_dir.unwrap_or_else(|| configs.configs_dir.join("schemas"));
+            write_schemas(output_dir.as_path())
+        }
+        Some(Command::ImportVdf { file, name, base }) => {
+            commands::import_layout(import_vdf, file.as_path(), name, base.as_str(), &configs)
+        }
+        Some(Command::ImportScc { file, name, base }) => commands::import_layout(
+            import_sccprofile,
+            file.as_path(),
+            name,
+            base.as_str(),
             &configs,
-            &termination_status,
-        ));
-    };
-
-    Ok(())
+        ),
+    }
 }
 
 // Don't use lazy_static with multiple threads.
@@ -1,10 +1,12 @@
 use color_eyre::eyre::{bail, Result};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
+use strum_macros::{AsRefStr, Display, EnumCount, EnumIter, EnumString};
 
 #[derive(
     PartialOrd,
     EnumIter,
+    EnumCount,
     EnumString,
     AsRefStr,
     Display,
@@ -17,6 +19,7 @@ use strum_macros::{AsRefStr, Display, EnumIter, EnumString};
     Debug,
     Serialize,
     Deserialize,
+    JsonSchema,
 )]
 // #[strum(serialize_all = "snake_case")]
 pub enum ButtonName {
@@ -59,6 +62,16 @@ pub enum ButtonName {
     ExtraBtn_SideL,
     ExtraBtn_SideR,
     ExtraBtnCentral,
+    // Motion gestures
+    Shake,
+    FlickUp,
+    FlickDown,
+    FlickLeft,
+    FlickRight,
+    TiltHoldUp,
+    TiltHoldDown,
+    TiltHoldLeft,
+    TiltHoldRight,
     //
     #[default]
     None,
@@ -117,6 +130,10 @@ pub enum AxisName {
     LowerTrigger_SideL,
     LowerTrigger_SideR,
     //
+    GyroPitch,
+    GyroRoll,
+    GyroYaw,
+    //
     #[default]
     None,
 }
@@ -218,6 +218,77 @@ impl std::ops::SubAssign<Vector> for Vector {
     }
 }
 
+#[derive(PartialEq, Copy, Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    #[inline]
+    pub fn magnitude(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
+    #[inline]
+    pub fn lerp(&self, target: Self, factor: f32) -> Self {
+        *self + (target - *self) * factor
+    }
+}
+
+impl std::ops::Add<Vector3> for Vector3 {
+    type Output = Vector3;
+
+    #[inline]
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+}
+
+impl std::ops::Sub<Vector3> for Vector3 {
+    type Output = Vector3;
+
+    #[inline]
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3 {
+            x: self.x - other.x,
+            y: self.y - other.y,
+            z: self.z - other.z,
+        }
+    }
+}
+
+impl std::ops::Mul<f32> for Vector3 {
+    type Output = Vector3;
+
+    #[inline]
+    fn mul(self, factor: f32) -> Vector3 {
+        Vector3 {
+            x: self.x * factor,
+            y: self.y * factor,
+            z: self.z * factor,
+        }
+    }
+}
+
+impl std::ops::Div<f32> for Vector3 {
+    type Output = Vector3;
+
+    #[inline]
+    fn div(self, divisor: f32) -> Vector3 {
+        Vector3 {
+            x: self.x / divisor,
+            y: self.y / divisor,
+            z: self.z / divisor,
+        }
+    }
+}
+
 #[inline]
 pub fn rotate_by_angle(point1: Vector, mut point2: Vector, rotation_angle: f32) -> Vector {
     let rotation_angle = rotation_angle * DEGREES_TO_RADIANS;
@@ -495,7 +566,7 @@ impl<T: ZoneValue> ZonesMapper<T> {
                 values[ind].clone(),
                 values[(ind + 1) % values.len()].clone(),
             ]
-                .concat();
+            .concat();
         }
 
         Self::_gen_from_any(
@@ -522,6 +593,28 @@ impl<T: ZoneValue> ZonesMapper<T> {
         }
     }
 
+    /// Ranges of angles with the same value: (first angle, last angle, value)
+    pub fn angle_table(&self) -> Vec<(Angle, Angle, String)> {
+        let mut table: Vec<(Angle, Angle, String)> = vec![];
+        for angle in 0..self.angle_to_value.len() {
+            let value = match &self.angle_to_value[angle] {
+                None => "None".to_string(),
+                Some(value) => value
+                    .iter()
+                    .map(|val| val.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+            match table.last_mut() {
+                Some((_, last_angle, prev_value)) if *prev_value == value => {
+                    *last_angle = angle;
+                }
+                _ => table.push((angle, angle, value)),
+            }
+        }
+        table
+    }
+
     fn _print_angle_to_zone(angle_to_zone: &[Option<ZoneNumber>; 360]) {
         for ind in 0..angle_to_zone.len() {
             let value = option_to_string(angle_to_zone[ind]);
@@ -0,0 +1,348 @@
+use crate::configs::GesturesConfigs;
+use crate::match_event::{AxisName, ButtonName, EventTypeName, TransformedEvent};
+use crate::math_ops::Vector3;
+use std::time::{Duration, Instant};
+
+// Low-pass factor that separates gravity from the controller's own movement
+const GRAVITY_SMOOTHING: f32 = 0.05;
+// Gestures are momentary. Keep them pressed long enough for games to notice
+const TAP_DURATION: Duration = Duration::from_millis(30);
+// A flick is a push and a stop. More direction changes within the shake window make a shake
+const FLICK_REVERSALS: usize = 2;
+
+fn gesture_event(button: ButtonName, pressed: bool) -> TransformedEvent {
+    TransformedEvent {
+        event_type: match pressed {
+            true => EventTypeName::ButtonPressed,
+            false => EventTypeName::ButtonReleased,
+        },
+        axis: AxisName::None,
+        button,
+        value: 0.0,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct GestureDetector {
+    cfg: GesturesConfigs,
+    gravity: Option<Vector3>,
+    last_flick: Option<Instant>,
+    // Fired once the shake window passes without turning into a shake
+    pending_flick: Option<(ButtonName, Instant)>,
+    shake_sign: f32,
+    shake_reversals: Vec<Instant>,
+    tilt_button: ButtonName,
+    tilt_since: Instant,
+    tilt_pressed: bool,
+    pending_releases: Vec<(ButtonName, Instant)>,
+}
+
+impl GestureDetector {
+    pub fn new(cfg: &GesturesConfigs) -> Self {
+        Self {
+            cfg: *cfg,
+            gravity: None,
+            last_flick: None,
+            pending_flick: None,
+            shake_sign: 0.0,
+            shake_reversals: vec![],
+            tilt_button: ButtonName::None,
+            tilt_since: Instant::now(),
+            tilt_pressed: false,
+            pending_releases: vec![],
+        }
+    }
+
+    pub fn update(&mut self, acceleration: Vector3, now: Instant) -> Vec<TransformedEvent> {
+        let mut events = vec![];
+        if !self.cfg.use_gestures {
+            return events;
+        }
+
+        self.release_expired(now, &mut events);
+
+        let gravity = match self.gravity {
+            None => acceleration,
+            Some(gravity) => gravity.lerp(acceleration, GRAVITY_SMOOTHING),
+        };
+        self.gravity = Some(gravity);
+        let movement = acceleration - gravity;
+
+        self.detect_flick(movement, now, &mut events);
+        self.detect_shake(movement, now, &mut events);
+        self.detect_tilt_hold(gravity, now, &mut events);
+
+        events
+    }
+
+    fn tap(&mut self, button: ButtonName, now: Instant, events: &mut Vec<TransformedEvent>) {
+        if self
+            .pending_releases
+            .iter()
+            .any(|(pending, _)| *pending == button)
+        {
+            return;
+        }
+        events.push(gesture_event(button, true));
+        self.pending_releases.push((button, now + TAP_DURATION));
+    }
+
+    fn release_expired(&mut self, now: Instant, events: &mut Vec<TransformedEvent>) {
+        self.pending_releases.retain(|(button, release_at)| {
+            if now >= *release_at {
+                events.push(gesture_event(*button, false));
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn detect_shake(
+        &mut self,
+        movement: Vector3,
+        now: Instant,
+        events: &mut Vec<TransformedEvent>,
+    ) {
+        let value = if movement.x.abs() >= movement.y.abs() {
+            movement.x
+        } else {
+            movement.y
+        };
+        if value.abs() < self.cfg.shake_threshold {
+            return;
+        }
+
+        let shake_window = self.cfg.shake_window;
+        self.shake_reversals
+            .retain(|time| now.duration_since(*time) <= shake_window);
+
+        let sign = value.signum();
+        if !self.shake_reversals.is_empty() && sign == self.shake_sign {
+            return;
+        }
+        self.shake_sign = sign;
+        self.shake_reversals.push(now);
+        if self.shake_reversals.len() > FLICK_REVERSALS {
+            self.pending_flick = None;
+        }
+
+        if self.shake_reversals.len() >= self.cfg.shake_reversals as usize {
+            self.shake_reversals.clear();
+            self.pending_flick = None;
+            self.tap(ButtonName::Shake, now, events);
+        }
+    }
+
+    fn detect_flick(
+        &mut self,
+        movement: Vector3,
+        now: Instant,
+        events: &mut Vec<TransformedEvent>,
+    ) {
+        if let Some((button, flick_time)) = self.pending_flick {
+            if now.duration_since(flick_time) >= self.cfg.shake_window {
+                self.pending_flick = None;
+                self.last_flick = Some(now);
+                self.tap(button, now, events);
+            }
+            return;
+        }
+        if let Some(last_flick) = self.last_flick {
+            if now.duration_since(last_flick) < self.cfg.flick_cooldown {
+                return;
+            }
+        }
+
+        let threshold = self.cfg.flick_threshold;
+        let button = if movement.x.abs() >= movement.y.abs() {
+            if movement.x.abs() < threshold {
+                return;
+            }
+            match movement.x > 0.0 {
+                true => ButtonName::FlickRight,
+                false => ButtonName::FlickLeft,
+            }
+        } else {
+            if movement.y.abs() < threshold {
+                return;
+            }
+            match movement.y > 0.0 {
+                true => ButtonName::FlickUp,
+                false => ButtonName::FlickDown,
+            }
+        };
+
+        self.pending_flick = Some((button, now));
+    }
+
+    fn detect_tilt_hold(
+        &mut self,
+        gravity: Vector3,
+        now: Instant,
+        events: &mut Vec<TransformedEvent>,
+    ) {
+        let magnitude = gravity.magnitude();
+        let button = if magnitude == 0.0 {
+            ButtonName::None
+        } else {
+            let direction = gravity / magnitude;
+            let threshold = self.cfg.tilt_threshold;
+
+            if direction.x >= threshold {
+                ButtonName::TiltHoldRight
+            } else if direction.x <= -threshold {
+                ButtonName::TiltHoldLeft
+            } else if direction.y >= threshold {
+                ButtonName::TiltHoldUp
+            } else if direction.y <= -threshold {
+                ButtonName::TiltHoldDown
+            } else {
+                ButtonName::None
+            }
+        };
+
+        if button != self.tilt_button {
+            if self.tilt_pressed {
+                events.push(gesture_event(self.tilt_button, false));
+                self.tilt_pressed = false;
+            }
+            self.tilt_button = button;
+            self.tilt_since = now;
+        }
+
+        if button != ButtonName::None
+            && !self.tilt_pressed
+            && now.duration_since(self.tilt_since) >= self.cfg.tilt_hold
+        {
+            events.push(gesture_event(button, true));
+            self.tilt_pressed = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REST: Vector3 = Vector3 {
+        x: 0.0,
+        y: 0.0,
+        z: 1.0,
+    };
+    const STEP_MS: u64 = 10;
+
+    fn vector(x: f32, y: f32, z: f32) -> Vector3 {
+        Vector3 { x, y, z }
+    }
+
+    fn gesture_detector() -> GestureDetector {
+        let cfg: GesturesConfigs = serde_yml::from_str(
+            "{ flick_threshold: 0.35, flick_cooldown_ms: 300, shake_threshold: 0.25, \
+            shake_reversals: 4, shake_window_ms: 600, tilt_angle: 40, tilt_hold_ms: 500 }",
+        )
+        .unwrap();
+        GestureDetector::new(&cfg.load_and_return().unwrap())
+    }
+
+    // Samples every STEP_MS. Returns the time, button and whether it's pressed
+    fn run(
+        gesture_detector: &mut GestureDetector,
+        duration_ms: u64,
+        acceleration_at: impl Fn(u64) -> Vector3,
+    ) -> Vec<(u64, ButtonName, bool)> {
+        let start = Instant::now();
+        let mut gestures = vec![];
+        for time_ms in (0..=duration_ms).step_by(STEP_MS as usize) {
+            let now = start + Duration::from_millis(time_ms);
+            for event in gesture_detector.update(acceleration_at(time_ms), now) {
+                let is_pressed = event.event_type == EventTypeName::ButtonPressed;
+                gestures.push((time_ms, event.button, is_pressed));
+            }
+        }
+        gestures
+    }
+
+    #[test]
+    fn flick_in_each_direction() {
+        let directions = [
+            (vector(0.5, 0.0, 1.0), ButtonName::FlickRight),
+            (vector(-0.5, 0.0, 1.0), ButtonName::FlickLeft),
+            (vector(0.0, 0.5, 1.0), ButtonName::FlickUp),
+            (vector(0.0, -0.5, 1.0), ButtonName::FlickDown),
+        ];
+        for (push, button) in directions {
+            // Push and stop
+            let stop = REST + (REST - push) * 0.8;
+            let gestures = run(&mut gesture_detector(), 1000, |time_ms| match time_ms {
+                50 => push,
+                100 => stop,
+                _ => REST,
+            });
+            // Fired after the shake window, released after the tap duration
+            assert_eq!(gestures, [(650, button, true), (680, button, false)]);
+        }
+    }
+
+    #[test]
+    fn shake_does_not_flick() {
+        let gestures = run(&mut gesture_detector(), 1500, |time_ms| match time_ms {
+            50 | 150 => vector(0.5, 0.0, 1.0),
+            100 | 200 => vector(-0.5, 0.0, 1.0),
+            _ => REST,
+        });
+        assert_eq!(
+            gestures,
+            [
+                (200, ButtonName::Shake, true),
+                (230, ButtonName::Shake, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn slow_swings_are_not_a_shake() {
+        // Reversals are further apart than the shake window
+        let gestures = run(&mut gesture_detector(), 3000, |time_ms| match time_ms {
+            50 | 1450 => vector(0.3, 0.0, 1.0),
+            750 | 2150 => vector(-0.3, 0.0, 1.0),
+            _ => REST,
+        });
+        assert!(gestures.is_empty());
+    }
+
+    #[test]
+    fn flicks_wait_for_cooldown() {
+        let push = vector(0.5, 0.0, 1.0);
+        let gestures = run(&mut gesture_detector(), 2000, |time_ms| match time_ms {
+            // Fired at 650. The second push is within the cooldown
+            50 | 700 | 1000 => push,
+            _ => REST,
+        });
+        assert_eq!(
+            gestures,
+            [
+                (650, ButtonName::FlickRight, true),
+                (680, ButtonName::FlickRight, false),
+                (1600, ButtonName::FlickRight, true),
+                (1630, ButtonName::FlickRight, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn tilt_is_held_while_tilted() {
+        let tilted = vector(0.8, 0.0, 0.6);
+        // Returns to rest slowly enough not to count as a movement
+        let gestures = run(&mut gesture_detector(), 3000, |time_ms| {
+            let back = time_ms.saturating_sub(1000) as f32 / 1000.0;
+            tilted.lerp(REST, back.min(1.0))
+        });
+
+        assert_eq!(gestures.len(), 2);
+        assert_eq!(gestures[0], (500, ButtonName::TiltHoldRight, true));
+        let (release_ms, button, is_pressed) = gestures[1];
+        assert_eq!((button, is_pressed), (ButtonName::TiltHoldRight, false));
+        assert!(release_ms > 1000 && release_ms < 3000);
+    }
+}
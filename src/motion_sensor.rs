@@ -0,0 +1,428 @@
+use crate::configs::{LayoutConfigs, MainConfigs, MotionSensorConfigs};
+use crate::file_ops::{read_yaml, write_yaml};
+use crate::math_ops::Vector3;
+use crate::pads_ops::wrap_orientation_diff;
+use color_eyre::eyre::Result;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Instant;
+
+const DEFAULT_CONTROLLER_ID: &str = "default";
+// How fast the bias follows the drift measured while the controller rests
+const DRIFT_SMOOTHING: f32 = 0.1;
+
+#[derive(Clone, Copy, Default, Debug, Serialize, Deserialize)]
+pub struct MotionBias {
+    // Orientation change per second while the controller rests
+    pub drift: Vector3,
+}
+
+#[inline]
+fn wrap_angles(value: Vector3) -> Vector3 {
+    Vector3 {
+        x: wrap_orientation_diff(value.x),
+        y: wrap_orientation_diff(value.y),
+        z: wrap_orientation_diff(value.z),
+    }
+}
+
+// Serial number is read through the open connection, so the dongle reports the paired controller
+pub fn controller_id(controller: &mut steamy_base::Controller) -> String {
+    match controller.serial() {
+        Ok(serial) => {
+            let serial: String = serial
+                .chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect();
+            match serial.is_empty() {
+                true => DEFAULT_CONTROLLER_ID.to_string(),
+                false => serial,
+            }
+        }
+        Err(err) => {
+            warn!("Cannot read controller serial number: {:?}", err);
+            DEFAULT_CONTROLLER_ID.to_string()
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MotionSensor {
+    cfg: MotionSensorConfigs,
+    bias: MotionBias,
+    calibration_dir: PathBuf,
+    controller_id: String,
+    // Bias is loaded or measured
+    is_calibrated: bool,
+    calibrating: bool,
+    calibration_start: Option<Instant>,
+
+    raw_orientation: Option<Vector3>,
+    correction: Vector3,
+    last_update: Option<Instant>,
+
+    last_acceleration: Option<Vector3>,
+    // Moment and orientation when the controller stopped moving
+    rest_start: Option<(Instant, Vector3)>,
+}
+
+impl MotionSensor {
+    pub fn new(configs: &MainConfigs, controller_id: &str) -> Result<Self> {
+        let mut motion_sensor = Self::from_cfg(
+            configs.motion_sensor_cfg,
+            configs.configs_dir.join("calibration"),
+            controller_id,
+        );
+        motion_sensor.update_layout(&configs.layout_configs)?;
+        Ok(motion_sensor)
+    }
+
+    fn from_cfg(cfg: MotionSensorConfigs, calibration_dir: PathBuf, controller_id: &str) -> Self {
+        Self {
+            cfg,
+            bias: MotionBias::default(),
+            calibration_dir,
+            controller_id: controller_id.to_string(),
+            is_calibrated: false,
+            calibrating: false,
+            calibration_start: None,
+            raw_orientation: None,
+            correction: Vector3::default(),
+            last_update: None,
+            last_acceleration: None,
+            rest_start: None,
+        }
+    }
+
+    // Layout that is switched to or reloaded can start using gyro or tilt
+    pub fn update_layout(&mut self, layout_configs: &LayoutConfigs) -> Result<()> {
+        if self.is_calibrated || self.calibrating || !layout_configs.uses_orientation() {
+            return Ok(());
+        }
+
+        let is_stored = self
+            .calibration_dir
+            .join(format!("{}.yaml", self.controller_id))
+            .exists();
+
+        if is_stored && !self.cfg.calibrate_on_start {
+            self.bias = read_yaml(self.calibration_dir.as_path(), self.controller_id.as_str())?;
+            self.is_calibrated = true;
+            debug!(
+                "Loaded motion bias for '{}': {:?}",
+                self.controller_id, self.bias
+            );
+        } else {
+            println!("Calibrating gyro. Keep the controller still");
+            self.calibrating = true;
+            self.calibration_start = None;
+        }
+        Ok(())
+    }
+
+    fn check_calibration_timeout(&mut self, now: Instant) {
+        let calibration_start = *self.calibration_start.get_or_insert(now);
+        if now.duration_since(calibration_start) < self.cfg.calibration_timeout {
+            return;
+        }
+        warn!(
+            "Gyro calibration timed out after {:?}: the controller didn't stay still. Using zero bias",
+            self.cfg.calibration_timeout
+        );
+        self.calibrating = false;
+        self.is_calibrated = true;
+        self.correction = Vector3::default();
+        self.rest_start = None;
+    }
+
+    /// Returns None until calibration is finished
+    pub fn correct_orientation(&mut self, raw: Vector3, now: Instant) -> Result<Option<Vector3>> {
+        self.raw_orientation = Some(raw);
+
+        if let Some(last_update) = self.last_update {
+            let elapsed = now.duration_since(last_update).as_secs_f32();
+            self.correction = wrap_angles(self.correction + self.bias.drift * elapsed);
+        }
+        self.last_update = Some(now);
+
+        self.check_rest(now)?;
+
+        if self.calibrating {
+            self.check_calibration_timeout(now);
+        }
+        if self.calibrating {
+            return Ok(None);
+        }
+        Ok(Some(wrap_angles(raw - self.correction)))
+    }
+
+    pub fn update_acceleration(&mut self, acceleration: Vector3, now: Instant) -> Result<()> {
+        let is_still = match self.last_acceleration {
+            None => false,
+            Some(last_acceleration) => {
+                (acceleration - last_acceleration).magnitude() <= self.cfg.still_threshold
+            }
+        };
+        self.last_acceleration = Some(acceleration);
+
+        if !is_still {
+            self.rest_start = None;
+            return Ok(());
+        }
+
+        self.check_rest(now)
+    }
+
+    fn check_rest(&mut self, now: Instant) -> Result<()> {
+        if !(self.calibrating || self.cfg.drift_correction) {
+            return Ok(());
+        }
+        let Some(raw_orientation) = self.raw_orientation else {
+            return Ok(());
+        };
+        if self.last_acceleration.is_none() {
+            return Ok(());
+        }
+
+        let Some((rest_start_time, rest_start_orientation)) = self.rest_start else {
+            self.rest_start = Some((now, raw_orientation));
+            return Ok(());
+        };
+
+        let required_duration = match self.calibrating {
+            true => self.cfg.calibration_duration,
+            false => self.cfg.still_duration,
+        };
+        let rest_duration = now.duration_since(rest_start_time);
+        if rest_duration < required_duration || rest_duration.is_zero() {
+            return Ok(());
+        }
+
+        let drift =
+            wrap_angles(raw_orientation - rest_start_orientation) / rest_duration.as_secs_f32();
+
+        if self.calibrating {
+            self.bias.drift = drift;
+            self.calibrating = false;
+            self.is_calibrated = true;
+            self.correction = Vector3::default();
+            write_yaml(
+                self.calibration_dir.as_path(),
+                self.controller_id.as_str(),
+                &self.bias,
+            )?;
+            println!("Gyro calibrated");
+        } else {
+            self.bias.drift = self.bias.drift.lerp(drift, DRIFT_SMOOTHING);
+        }
+        debug!("Motion bias: {:?}", self.bias);
+
+        self.rest_start = Some((now, raw_orientation));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_ops::yaml_filepath;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::ops::RangeInclusive;
+    use std::path::Path;
+    use std::time::Duration;
+
+    const CONTROLLER_ID: &str = "FAKE123";
+    const STEP: Duration = Duration::from_millis(100);
+    // Orientation change per second
+    const DRIFT: f32 = 0.01;
+
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(test_name: &str) -> Self {
+            let path = temp_dir().join(format!(
+                "joystick-motion-sensor-{}-{}",
+                test_name,
+                std::process::id()
+            ));
+            let _ = remove_dir_all(&path);
+            create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+
+    fn motion_sensor_cfg() -> MotionSensorConfigs {
+        let mut cfg: MotionSensorConfigs = serde_yml::from_str(
+            "{ calibrate_on_start: false, calibration_ms: 1000, calibration_timeout_ms: 2000, \
+            still_threshold: 0.002, still_ms: 1000, drift_correction: true }",
+        )
+        .unwrap();
+        cfg.load().unwrap();
+        cfg
+    }
+
+    fn gyro_layout() -> LayoutConfigs {
+        let layouts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/layouts");
+        LayoutConfigs::load("valheim", layouts_dir).unwrap()
+    }
+
+    fn new_sensor(calibration_dir: &Path, controller_id: &str) -> MotionSensor {
+        let mut motion_sensor = MotionSensor::from_cfg(
+            motion_sensor_cfg(),
+            calibration_dir.to_path_buf(),
+            controller_id,
+        );
+        motion_sensor.update_layout(&gyro_layout()).unwrap();
+        motion_sensor
+    }
+
+    fn drifted(seconds: f32) -> Vector3 {
+        Vector3 {
+            x: DRIFT * seconds,
+            y: 0.0,
+            z: 0.0,
+        }
+    }
+
+    // Resting controller: same acceleration, orientation drifts at a constant rate
+    fn feed_still(
+        motion_sensor: &mut MotionSensor,
+        start: Instant,
+        steps: RangeInclusive<u32>,
+    ) -> Option<Vector3> {
+        let mut corrected = None;
+        for step in steps {
+            let now = start + STEP * step;
+            let seconds = now.duration_since(start).as_secs_f32();
+            corrected = motion_sensor
+                .correct_orientation(drifted(seconds), now)
+                .unwrap();
+            motion_sensor
+                .update_acceleration(Vector3::default(), now)
+                .unwrap();
+        }
+        corrected
+    }
+
+    #[test]
+    fn bias_is_estimated_from_resting_samples() {
+        let test_dir = TestDir::new("estimate");
+        let mut motion_sensor = new_sensor(&test_dir.0, CONTROLLER_ID);
+        let start = Instant::now();
+
+        // Rest starts with the second sample, so 1s of it takes 11 steps
+        assert_eq!(feed_still(&mut motion_sensor, start, 0..=10), None);
+        assert!(feed_still(&mut motion_sensor, start, 11..=11).is_some());
+
+        assert!(motion_sensor.is_calibrated);
+        assert!((motion_sensor.bias.drift.x - DRIFT).abs() < 1e-4);
+        assert_eq!(motion_sensor.bias.drift.y, 0.0);
+    }
+
+    #[test]
+    fn bias_is_stored_per_controller() {
+        let test_dir = TestDir::new("stored");
+        let mut motion_sensor = new_sensor(&test_dir.0, CONTROLLER_ID);
+        feed_still(&mut motion_sensor, Instant::now(), 0..=11);
+        assert!(yaml_filepath(&test_dir.0, CONTROLLER_ID).exists());
+
+        let mut reloaded = new_sensor(&test_dir.0, CONTROLLER_ID);
+        assert!(reloaded.is_calibrated);
+        assert_eq!(reloaded.bias.drift, motion_sensor.bias.drift);
+        // No calibration wait
+        let now = Instant::now();
+        assert!(reloaded
+            .correct_orientation(Vector3::default(), now)
+            .unwrap()
+            .is_some());
+
+        let mut other_controller = new_sensor(&test_dir.0, "OTHER456");
+        assert!(!other_controller.is_calibrated);
+        assert_eq!(
+            other_controller
+                .correct_orientation(Vector3::default(), now)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn drift_is_corrected_after_still_time() {
+        let test_dir = TestDir::new("drift");
+        write_yaml(&test_dir.0, CONTROLLER_ID, &MotionBias::default()).unwrap();
+        let mut motion_sensor = new_sensor(&test_dir.0, CONTROLLER_ID);
+        let start = Instant::now();
+
+        feed_still(&mut motion_sensor, start, 0..=10);
+        assert_eq!(motion_sensor.bias.drift, Vector3::default());
+
+        feed_still(&mut motion_sensor, start, 11..=11);
+        let expected = Vector3::default().lerp(drifted(1.0), DRIFT_SMOOTHING);
+        assert!((motion_sensor.bias.drift.x - expected.x).abs() < 1e-5);
+    }
+
+    #[test]
+    fn moving_controller_keeps_the_bias() {
+        let test_dir = TestDir::new("moving");
+        write_yaml(&test_dir.0, CONTROLLER_ID, &MotionBias::default()).unwrap();
+        let mut motion_sensor = new_sensor(&test_dir.0, CONTROLLER_ID);
+        let start = Instant::now();
+
+        for step in 0..=30 {
+            let now = start + STEP * step;
+            let seconds = now.duration_since(start).as_secs_f32();
+            motion_sensor
+                .correct_orientation(drifted(seconds), now)
+                .unwrap();
+            let acceleration = Vector3 {
+                x: step as f32 * 0.01,
+                y: 0.0,
+                z: 0.0,
+            };
+            motion_sensor
+                .update_acceleration(acceleration, now)
+                .unwrap();
+        }
+        assert_eq!(motion_sensor.bias.drift, Vector3::default());
+    }
+
+    #[test]
+    fn calibration_times_out_if_never_still() {
+        let test_dir = TestDir::new("timeout");
+        let mut motion_sensor = new_sensor(&test_dir.0, CONTROLLER_ID);
+        let start = Instant::now();
+
+        let mut corrected = vec![];
+        for step in 0..=20 {
+            let now = start + STEP * step;
+            corrected.push(
+                motion_sensor
+                    .correct_orientation(Vector3::default(), now)
+                    .unwrap(),
+            );
+            let acceleration = Vector3 {
+                x: step as f32 * 0.01,
+                y: 0.0,
+                z: 0.0,
+            };
+            motion_sensor
+                .update_acceleration(acceleration, now)
+                .unwrap();
+        }
+
+        // Timeout is 2s
+        assert!(corrected[..20].iter().all(Option::is_none));
+        assert_eq!(corrected[20], Some(Vector3::default()));
+        assert!(motion_sensor.is_calibrated);
+        assert_eq!(motion_sensor.bias.drift, Vector3::default());
+        assert!(!yaml_filepath(&test_dir.0, CONTROLLER_ID).exists());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::exec_or_eyre;
+use color_eyre::eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use strum_macros::Display;
+#[cfg(feature = "use_buffered_input")]
+use universal_input::EventParams;
+use universal_input::{InputEmulator, KeyCode, OS_Input_Coord};
+
+pub trait OutputSink {
+    fn press(&mut self, key_code: KeyCode) -> Result<()>;
+
+    fn release(&mut self, key_code: KeyCode) -> Result<()>;
+
+    fn move_mouse(&mut self, x: OS_Input_Coord, y: OS_Input_Coord) -> Result<()>;
+
+    fn scroll(&mut self, x: OS_Input_Coord, y: OS_Input_Coord) -> Result<()>;
+
+    /// Called once at the end of every writing iteration
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct InputEmulatorSink {
+    input_emulator: InputEmulator,
+    #[cfg(feature = "use_buffered_input")]
+    write_buffer: Vec<EventParams>,
+}
+
+impl InputEmulatorSink {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            input_emulator: InputEmulator::new()?,
+            #[cfg(feature = "use_buffered_input")]
+            write_buffer: vec![],
+        })
+    }
+}
+
+impl OutputSink for InputEmulatorSink {
+    fn press(&mut self, key_code: KeyCode) -> Result<()> {
+        #[cfg(feature = "use_buffered_input")]
+        {
+            self.write_buffer
+                .extend(self.input_emulator.buffered_press(key_code)?);
+        }
+        #[cfg(not(feature = "use_buffered_input"))]
+        {
+            self.input_emulator.press(key_code)?;
+        }
+        Ok(())
+    }
+
+    fn release(&mut self, key_code: KeyCode) -> Result<()> {
+        #[cfg(feature = "use_buffered_input")]
+        {
+            self.write_buffer
+                .extend(self.input_emulator.buffered_release(key_code)?);
+        }
+        #[cfg(not(feature = "use_buffered_input"))]
+        {
+            self.input_emulator.release(key_code)?;
+        }
+        Ok(())
+    }
+
+    fn move_mouse(&mut self, x: OS_Input_Coord, y: OS_Input_Coord) -> Result<()> {
+        #[cfg(feature = "gradual_mouse")]
+        {
+            // println!("Gradual Mouse");
+            #[cfg(feature = "use_buffered_input")]
+            {
+                self.write_buffer
+                    .extend(self.input_emulator.buffered_gradual_move_mouse(x, y));
+            }
+            #[cfg(not(feature = "use_buffered_input"))]
+            {
+                #[cfg(feature = "use_raw_input")]
+                {
+                    self.input_emulator.gradual_move_mouse_raw(x, y)?;
+                }
+                #[cfg(not(feature = "use_raw_input"))]
+                {
+                    self.input_emulator.gradual_move_mouse(x, y)?;
+                }
+            }
+        }
+        #[cfg(not(feature = "gradual_mouse"))]
+        {
+            self.input_emulator.move_mouse(x, y)?;
+        }
+        Ok(())
+    }
+
+    fn scroll(&mut self, x: OS_Input_Coord, y: OS_Input_Coord) -> Result<()> {
+        #[cfg(feature = "gradual_scroll")]
+        {
+            // println!("Gradual Scroll");
+            #[cfg(feature = "use_buffered_input")]
+            {
+                self.write_buffer
+                    .extend(self.input_emulator.buffered_gradual_scroll(x, y));
+            }
+            #[cfg(not(feature = "use_buffered_input"))]
+            {
+                #[cfg(feature = "use_raw_input")]
+                {
+                    self.input_emulator.gradual_scroll_raw(x, y)?;
+                }
+                #[cfg(not(feature = "use_raw_input"))]
+                {
+                    self.input_emulator.gradual_scroll(x, y)?;
+                }
+            }
+        }
+        #[cfg(not(feature = "gradual_scroll"))]
+        {
+            if x != 0 {
+                self.input_emulator.scroll_x(x)?;
+            }
+            if y != 0 {
+                self.input_emulator.scroll_y(y)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        #[cfg(feature = "use_buffered_input")]
+        {
+            self.input_emulator.write_buffer(&self.write_buffer)?;
+            self.write_buffer.clear()
+        }
+        Ok(())
+    }
+}
+
+#[derive(Display, PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum OutputCommand {
+    Pressed(KeyCode),
+    Released(KeyCode),
+    MouseMoved(OS_Input_Coord, OS_Input_Coord),
+    Scrolled(OS_Input_Coord, OS_Input_Coord),
+}
+
+// Captures commands instead of sending them to the OS.
+// Clones share the same storage, so the writing thread can own one of them
+#[derive(Clone, Default, Debug)]
+pub struct RecordingSink {
+    commands: Arc<Mutex<Vec<OutputCommand>>>,
+}
+
+impl RecordingSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn commands(&self) -> Result<Vec<OutputCommand>> {
+        Ok(exec_or_eyre!(self.commands.lock())?.clone())
+    }
+
+    pub fn take_commands(&self) -> Result<Vec<OutputCommand>> {
+        Ok(std::mem::take(&mut *exec_or_eyre!(self.commands.lock())?))
+    }
+
+    fn push(&self, command: OutputCommand) -> Result<()> {
+        exec_or_eyre!(self.commands.lock())?.push(command);
+        Ok(())
+    }
+}
+
+impl OutputSink for RecordingSink {
+    fn press(&mut self, key_code: KeyCode) -> Result<()> {
+        self.push(OutputCommand::Pressed(key_code))
+    }
+
+    fn release(&mut self, key_code: KeyCode) -> Result<()> {
+        self.push(OutputCommand::Released(key_code))
+    }
+
+    fn move_mouse(&mut self, x: OS_Input_Coord, y: OS_Input_Coord) -> Result<()> {
+        self.push(OutputCommand::MouseMoved(x, y))
+    }
+
+    fn scroll(&mut self, x: OS_Input_Coord, y: OS_Input_Coord) -> Result<()> {
+        self.push(OutputCommand::Scrolled(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configs::LayoutConfigs;
+    use crate::match_event::ButtonName;
+    use crate::process_event::{ButtonEvent, MouseEvent, PadStickEvent};
+    use crate::utils::create_channel;
+    use crate::writing_thread::EventWriter;
+    use std::path::Path;
+
+    fn desktop_writer() -> Result<EventWriter<RecordingSink>> {
+        let layouts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/layouts");
+        let layout_configs = LayoutConfigs::load("desktop", layouts_dir)?;
+        EventWriter::from_layout(&layout_configs, false, RecordingSink::new())
+    }
+
+    #[test]
+    fn button_press_and_release() -> Result<()> {
+        let mut event_writer = desktop_writer()?;
+        let (_mouse_sender, mouse_receiver) = create_channel::<MouseEvent>(-1);
+        let (button_sender, button_receiver) = create_channel(-1);
+
+        button_sender.send(ButtonEvent::Pressed(ButtonName::BtnDown_SideR))?;
+        event_writer.step(&mouse_receiver, &button_receiver)?;
+        assert_eq!(
+            event_writer.sink().take_commands()?,
+            [OutputCommand::Pressed(KeyCode::KEY_DOWN)]
+        );
+
+        button_sender.send(ButtonEvent::Released(ButtonName::BtnDown_SideR))?;
+        event_writer.step(&mouse_receiver, &button_receiver)?;
+        assert_eq!(
+            event_writer.sink().take_commands()?,
+            [OutputCommand::Released(KeyCode::KEY_DOWN)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_codes_are_released_in_reverse() -> Result<()> {
+        let mut event_writer = desktop_writer()?;
+        let (_mouse_sender, mouse_receiver) = create_channel::<MouseEvent>(-1);
+        let (button_sender, button_receiver) = create_channel(-1);
+
+        button_sender.send(ButtonEvent::Pressed(ButtonName::UpperTrigger_SideL))?;
+        button_sender.send(ButtonEvent::Released(ButtonName::UpperTrigger_SideL))?;
+        event_writer.step(&mouse_receiver, &button_receiver)?;
+        assert_eq!(
+            event_writer.sink().take_commands()?,
+            [
+                OutputCommand::Pressed(KeyCode::KEY_LEFTCTRL),
+                OutputCommand::Pressed(KeyCode::KEY_Z),
+                OutputCommand::Released(KeyCode::KEY_Z),
+                OutputCommand::Released(KeyCode::KEY_LEFTCTRL),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn right_pad_moves_mouse() -> Result<()> {
+        let mut event_writer = desktop_writer()?;
+        let (mouse_sender, mouse_receiver) = create_channel(-1);
+        let (_button_sender, button_receiver) = create_channel::<ButtonEvent>(-1);
+
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::FingerPut))?;
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedX(0.1)))?;
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedY(0.1)))?;
+        event_writer.step(&mouse_receiver, &button_receiver)?;
+        // Nothing to compare the first position with
+        assert!(event_writer.sink().take_commands()?.is_empty());
+
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedX(0.3)))?;
+        event_writer.step(&mouse_receiver, &button_receiver)?;
+        let commands = event_writer.sink().take_commands()?;
+        assert!(
+            matches!(commands.as_slice(), [OutputCommand::MouseMoved(x, 0)] if *x > 0),
+            "{:?}",
+            commands
+        );
+        Ok(())
+    }
+}
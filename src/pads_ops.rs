@@ -1,7 +1,7 @@
 use crate::buttons_state::ButtonsState;
 use crate::configs::{
-    AxisCorrection, AxisCorrectionConfigs, FingerRotationConfigs, JitterThresholdConfigs,
-    ZoneMappingConfigs,
+    AxisCorrection, AxisCorrectionConfigs, FingerRotationConfigs, GyroConfigs,
+    JitterThresholdConfigs, TiltConfigs, ZoneMappingConfigs,
 };
 use crate::math_ops::{rotate_around_center, Vector, ZoneValue, ZonesMapper};
 use crate::pads_ops::CoordState::Value;
@@ -201,12 +201,40 @@ impl CoordsDiff {
         }
     }
 
+    #[inline]
+    pub fn convert_per_axis(&self, x_multiplier: u16, y_multiplier: u16) -> ConvertedCoordsDiff {
+        ConvertedCoordsDiff {
+            x: convert_diff(self.x, x_multiplier),
+            y: convert_diff(self.y, y_multiplier),
+        }
+    }
+
+    // Orientation angles wrap around from 1.0 to -1.0 (and back) after a full turn
+    #[inline]
+    pub fn wrap_around(&self) -> Self {
+        Self {
+            x: wrap_orientation_diff(self.x),
+            y: wrap_orientation_diff(self.y),
+        }
+    }
+
     #[inline]
     pub fn is_any_changes(&self) -> bool {
         self.x != 0.0 || self.y != 0.0
     }
 }
 
+#[inline]
+pub fn wrap_orientation_diff(value: f32) -> f32 {
+    if value > 1.0 {
+        value - 2.0
+    } else if value < -1.0 {
+        value + 2.0
+    } else {
+        value
+    }
+}
+
 #[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct ConvertedCoordsDiff {
     pub x: OS_Input_Coord,
@@ -439,6 +467,10 @@ pub struct PadsCoords {
     pub left_pad: CoordsHistoryState,
     pub right_pad: CoordsHistoryState,
     pub stick: CoordsHistoryState,
+    // X: yaw, Y: pitch
+    pub gyro: CoordsHistoryState,
+    // X: roll, Y: pitch. Already converted to stick values
+    pub tilt: CoordsHistoryState,
 }
 
 impl PadsCoords {
@@ -446,6 +478,7 @@ impl PadsCoords {
         finger_rotation_cfg: &FingerRotationConfigs,
         axis_correction_cfg: &AxisCorrectionConfigs,
         jitter_threshold_cfg: &JitterThresholdConfigs,
+        gyro_cfg: &GyroConfigs,
     ) -> Self {
         let use_rotation = finger_rotation_cfg.use_rotation;
 
@@ -468,6 +501,13 @@ impl PadsCoords {
                 axis_correction_cfg.stick,
                 jitter_threshold_cfg.stick,
             ),
+            gyro: CoordsHistoryState::new(
+                0,
+                false,
+                AxisCorrection::default(),
+                gyro_cfg.jitter_threshold,
+            ),
+            tilt: CoordsHistoryState::new(0, false, AxisCorrection::default(), 0.0),
         }
     }
 
@@ -476,6 +516,8 @@ impl PadsCoords {
         self.left_pad.reset_all();
         self.right_pad.reset_all();
         self.stick.reset_all();
+        self.gyro.reset_all();
+        self.tilt.reset_all();
     }
 
     #[inline]
@@ -483,6 +525,8 @@ impl PadsCoords {
         self.left_pad.reset_current();
         self.right_pad.reset_current();
         self.stick.reset_current();
+        self.gyro.reset_current();
+        self.tilt.reset_current();
     }
 
     // pub fn set_prev_if_cur_is_none(&mut self) {
@@ -496,6 +540,8 @@ impl PadsCoords {
         self.left_pad.update();
         self.right_pad.update();
         self.stick.update();
+        self.gyro.update();
+        self.tilt.update();
     }
 
     // #[inline]
@@ -506,6 +552,54 @@ impl PadsCoords {
     // }
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct TiltState {
+    pub raw: Vector,
+    pub neutral: Vector,
+    max_value: f32,
+    deadzone: f32,
+}
+
+impl TiltState {
+    pub fn new(tilt_cfg: &TiltConfigs) -> Self {
+        let neutral = Vector {
+            x: tilt_cfg.neutral.x,
+            y: tilt_cfg.neutral.y,
+        };
+        Self {
+            raw: neutral,
+            neutral,
+            max_value: tilt_cfg.max_value,
+            deadzone: tilt_cfg.deadzone,
+        }
+    }
+
+    #[inline]
+    pub fn recenter(&mut self) {
+        self.neutral = self.raw;
+    }
+
+    #[inline]
+    fn to_stick_value(&self, raw: f32, neutral: f32) -> f32 {
+        let value = (wrap_orientation_diff(raw - neutral) / self.max_value).clamp(-1.0, 1.0);
+        if value.abs() <= self.deadzone {
+            0.0
+        } else {
+            value
+        }
+    }
+
+    #[inline]
+    pub fn stick_x(&self) -> f32 {
+        self.to_stick_value(self.raw.x, self.neutral.x)
+    }
+
+    #[inline]
+    pub fn stick_y(&self) -> f32 {
+        self.to_stick_value(self.raw.y, self.neutral.y)
+    }
+}
+
 #[derive(PartialEq, Copy, Clone, Default, Debug, Serialize, Deserialize)]
 pub struct GradualMove {
     pub x_direction: OS_Input_Coord,
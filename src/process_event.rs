@@ -1,11 +1,17 @@
-use crate::configs::{AxisCorrectionConfigs, LayoutConfigs, MainConfigs};
+use crate::configs::{
+    AxisCorrectionConfigs, GyroActivation, GyroConfigs, LayoutConfigs, MainConfigs,
+};
+use crate::exec_or_eyre;
+use crate::key_codes::LayoutSwitch;
+use crate::layout_watcher::LayoutWatcher;
 use crate::match_event::*;
 use crate::math_ops::{apply_pad_stick_correction, RangeConverterBuilder};
 use crate::process_event::ButtonEvent::{Pressed, Released};
 use crate::process_event::PadStickEvent::{FingerLifted, FingerPut};
+use crate::recorder::{EventRecorder, SharedRecorder};
 use crate::utils::create_channel;
 use color_eyre::eyre::{bail, Result};
-use log::debug;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
@@ -22,72 +28,217 @@ pub enum PadStickEvent {
     MovedY(f32),
 }
 
+#[derive(Display, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum OrientationEvent {
+    Pitch(f32),
+    Roll(f32),
+    Yaw(f32),
+}
+
 #[derive(Display, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum MouseEvent {
     LeftPad(PadStickEvent),
     RightPad(PadStickEvent),
     Stick(PadStickEvent),
+    Orientation(OrientationEvent),
+    GyroActive(bool),
+    TiltRecentered,
+    ControllerDisconnected,
     ModeSwitched,
     Reset,
 }
 
-#[derive(Display, Copy, Clone, Debug, Serialize, Deserialize)]
+#[derive(Display, Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ButtonEvent {
     Pressed(ButtonName),
     Released(ButtonName),
 }
 
+impl ButtonEvent {
+    pub fn button_name(&self) -> ButtonName {
+        match self {
+            ButtonEvent::Pressed(button_name) | ButtonEvent::Released(button_name) => *button_name,
+        }
+    }
+}
+
 pub type MouseSender = Sender<MouseEvent>;
 pub type MouseReceiver = Receiver<MouseEvent>;
 
 pub type ButtonSender = Sender<ButtonEvent>;
 pub type ButtonReceiver = Receiver<ButtonEvent>;
 
+pub type LayoutSender = Sender<LayoutConfigs>;
+pub type LayoutReceiver = Receiver<LayoutConfigs>;
+
+pub type LayoutSwitchSender = Sender<LayoutSwitch>;
+pub type LayoutSwitchReceiver = Receiver<LayoutSwitch>;
+
 #[derive(Clone, Debug)]
 pub struct SharedInfo {
     pub mouse_sender: MouseSender,
     pub mouse_receiver: MouseReceiver,
     pub button_sender: ButtonSender,
     pub button_receiver: ButtonReceiver,
+    pub layout_sender: LayoutSender,
+    pub layout_receiver: LayoutReceiver,
     //
     pub RESET_BTN: ButtonName,
     pub SWITCH_MODE_BTN: ButtonName,
     //
     pub layout_configs: LayoutConfigs,
+    layout_watcher: LayoutWatcher,
+    layout_playlist: Vec<String>,
+    // Switches are requested while processing events and applied between them
+    layout_switch_sender: LayoutSwitchSender,
+    layout_switch_receiver: LayoutSwitchReceiver,
+    pub recorder: Option<SharedRecorder>,
+    // Events are printed instead of being processed
+    pub dump_events: bool,
 }
 
 impl SharedInfo {
-    pub fn new(configs: &MainConfigs) -> Self {
+    pub fn new(configs: &MainConfigs) -> Result<Self> {
         let commands_channel_size = configs.general.commands_channel_size;
 
         let (mouse_sender, mouse_receiver) = create_channel(commands_channel_size);
         let (button_sender, button_receiver) = create_channel(commands_channel_size);
+        let (layout_sender, layout_receiver) = create_channel(-1);
+        let (layout_switch_sender, layout_switch_receiver) = create_channel(-1);
+
+        let recorder = match &configs.recording_dir {
+            None => None,
+            Some(recording_dir) => Some(EventRecorder::create_shared(
+                recording_dir,
+                configs.backend,
+                configs.layout_names_cfg.buttons_layout_name.as_str(),
+            )?),
+        };
+
+        let layout_watcher = LayoutWatcher::new(
+            configs.layouts_dir(),
+            configs.layout_names_cfg.buttons_layout_name.as_str(),
+        );
 
         let layout_configs = configs.layout_configs.clone();
-        Self {
+        Ok(Self {
             mouse_sender,
             mouse_receiver,
             button_sender,
             button_receiver,
+            layout_sender,
+            layout_receiver,
             RESET_BTN: layout_configs.buttons_layout.reset_btn,
             SWITCH_MODE_BTN: layout_configs.buttons_layout.switch_mode_btn,
             layout_configs,
+            layout_watcher,
+            layout_playlist: configs.layout_names_cfg.playlist.clone(),
+            layout_switch_sender,
+            layout_switch_receiver,
+            recorder,
+            dump_events: configs.dump_events,
+        })
+    }
+
+    pub fn layout_switch_sender(&self) -> LayoutSwitchSender {
+        self.layout_switch_sender.clone()
+    }
+
+    fn playlist_neighbour(&self, step: isize) -> Option<String> {
+        let len = self.layout_playlist.len() as isize;
+        if len == 0 {
+            return None;
+        }
+        let current_name = self.layout_watcher.layout_name();
+        let index = match self
+            .layout_playlist
+            .iter()
+            .position(|layout_name| layout_name == current_name)
+        {
+            Some(index) => (index as isize + step).rem_euclid(len),
+            // Layout is not in the playlist
+            None => match step > 0 {
+                true => 0,
+                false => len - 1,
+            },
+        };
+        Some(self.layout_playlist[index as usize].clone())
+    }
+
+    fn switched_layout(&mut self, layout_switch: LayoutSwitch) -> Option<LayoutConfigs> {
+        let layout_name = match layout_switch {
+            LayoutSwitch::Next => self.playlist_neighbour(1),
+            LayoutSwitch::Previous => self.playlist_neighbour(-1),
+            LayoutSwitch::Named(layout_name) => Some(layout_name),
+        };
+        let Some(layout_name) = layout_name else {
+            warn!("Layout playlist is empty");
+            return None;
+        };
+
+        match self.layout_watcher.switch_to(layout_name.as_str()) {
+            Ok(layout_configs) => {
+                println!("Switched to '{}' layout", layout_name);
+                Some(layout_configs)
+            }
+            Err(err) => {
+                warn!("Cannot switch to '{}' layout: {}", layout_name, err);
+                None
+            }
         }
     }
 
+    // Called from the input loops between events. Applies requested switches and file changes.
+    // Writing thread gets the new layout through the channel.
+    // Returns true if the layout was swapped
+    pub fn update_layout(&mut self) -> Result<bool> {
+        let mut new_layout = None;
+        for layout_switch in self.layout_switch_receiver.try_iter().collect::<Vec<_>>() {
+            if let Some(layout_configs) = self.switched_layout(layout_switch) {
+                new_layout = Some(layout_configs);
+            }
+        }
+        if new_layout.is_none() {
+            new_layout = self.layout_watcher.check();
+            if new_layout.is_some() {
+                println!("Layout reloaded");
+            }
+        }
+        let Some(layout_configs) = new_layout else {
+            return Ok(false);
+        };
+
+        self.RESET_BTN = layout_configs.buttons_layout.reset_btn;
+        self.SWITCH_MODE_BTN = layout_configs.buttons_layout.switch_mode_btn;
+        self.layout_sender.send(layout_configs.clone())?;
+        self.layout_configs = layout_configs;
+        Ok(true)
+    }
+
     pub fn release_all_hard(&self) -> Result<()> {
         self.button_sender.send(Released(self.RESET_BTN))?;
         Ok(())
     }
+
+    // Unlike RESET_BTN, keeps the mouse mode
+    pub fn reset_controller_state(&self) -> Result<()> {
+        self.mouse_sender.send(MouseEvent::ControllerDisconnected)?;
+        self.release_all_hard()
+    }
 }
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize)]
 pub struct ImplementationSpecificCfg {
     triggers_range_converter: RangeConverterBuilder<f32>,
+    discard_zero_pad_values: bool,
 }
 
 impl ImplementationSpecificCfg {
-    pub fn new(trigger_input_min: f32, trigger_input_max: f32) -> Self {
+    pub fn new(
+        trigger_input_min: f32,
+        trigger_input_max: f32,
+        discard_zero_pad_values: bool,
+    ) -> Self {
         Self {
             triggers_range_converter: RangeConverterBuilder::build(
                 trigger_input_min,
@@ -95,6 +246,7 @@ impl ImplementationSpecificCfg {
                 0.0,
                 1.0,
             ),
+            discard_zero_pad_values,
         }
     }
 }
@@ -117,6 +269,18 @@ pub fn process_event(
         }
     };
 
+    if let Some(recorder) = &shared_info.recorder {
+        exec_or_eyre!(recorder.lock())?.record(&event, impl_cfg)?;
+    }
+
+    if shared_info.dump_events {
+        println!(
+            "{} {} {} {:.3}",
+            event.event_type, event.button, event.axis, event.value
+        );
+        return Ok(());
+    }
+
     match transform_triggers(&mut event, &shared_info.layout_configs, impl_cfg) {
         TransformStatus::Discarded | TransformStatus::Handled => {
             return Ok(());
@@ -137,7 +301,7 @@ pub fn process_event(
         TransformStatus::Unchanged => {}
     };
 
-    match process_pad_stick(&mut event, shared_info)? {
+    match process_pad_stick(&mut event, shared_info, impl_cfg)? {
         TransformStatus::Discarded | TransformStatus::Handled => {
             return Ok(());
         }
@@ -213,9 +377,28 @@ fn apply_correction(event: &mut TransformedEvent, shared_info: &SharedInfo) {
     }
 }
 
+fn gyro_activation(event: &TransformedEvent, gyro_cfg: &GyroConfigs) -> Option<bool> {
+    if !gyro_cfg.use_gyro || event.button != gyro_cfg.enable_btn {
+        return None;
+    }
+
+    let is_pressed = match event.event_type {
+        EventTypeName::ButtonPressed => true,
+        EventTypeName::ButtonReleased => false,
+        EventTypeName::AxisChanged => return None,
+    };
+
+    match gyro_cfg.activation {
+        GyroActivation::AlwaysOn => None,
+        GyroActivation::Hold => Some(is_pressed),
+        GyroActivation::Ratchet => Some(!is_pressed),
+    }
+}
+
 pub fn process_pad_stick(
     event: &mut TransformedEvent,
     shared_info: &SharedInfo,
+    impl_cfg: &ImplementationSpecificCfg,
 ) -> Result<TransformStatus> {
     let send_mouse_event = |mouse_event: MouseEvent| -> Result<()> {
         shared_info.mouse_sender.send(mouse_event)?;
@@ -245,13 +428,50 @@ pub fn process_pad_stick(
                     send_mouse_event(MouseEvent::ModeSwitched)?;
                 }
                 return Ok(TransformStatus::Handled);
+            } else if let Some(layout_switch) = shared_info
+                .layout_configs
+                .buttons_layout
+                .layout_switches
+                .get(&event.button)
+            {
+                if event.event_type == EventTypeName::ButtonReleased {
+                    shared_info
+                        .layout_switch_sender
+                        .send(layout_switch.clone())?;
+                }
+                return Ok(TransformStatus::Handled);
             };
         }
     };
 
+    if let Some(is_active) = gyro_activation(event, &shared_info.layout_configs.gyro_cfg) {
+        send_mouse_event(MouseEvent::GyroActive(is_active))?;
+        // Button can still have its own binding
+        return Ok(TransformStatus::Unchanged);
+    }
+
+    let tilt_cfg = &shared_info.layout_configs.tilt_cfg;
+    if tilt_cfg.use_tilt
+        && tilt_cfg.recenter_btn != ButtonName::None
+        && event.button == tilt_cfg.recenter_btn
+    {
+        if event.event_type == EventTypeName::ButtonReleased {
+            send_mouse_event(MouseEvent::TiltRecentered)?;
+        }
+        return Ok(TransformStatus::Unchanged);
+    }
+
     if event.event_type == EventTypeName::AxisChanged {
-        #[cfg(not(feature = "use_steamy"))]
-        {
+        match event.axis {
+            AxisName::GyroPitch | AxisName::GyroRoll | AxisName::GyroYaw => {
+                if !shared_info.layout_configs.uses_orientation() {
+                    return Ok(TransformStatus::Discarded);
+                }
+            }
+            _ => {}
+        }
+
+        if impl_cfg.discard_zero_pad_values {
             // Was needed for gilrs. Now causes various bugs
             // Discard 0.0 events for pads
 
@@ -277,6 +497,13 @@ pub fn process_pad_stick(
             AxisName::PadY_SideR => Some(MouseEvent::RightPad(PadStickEvent::MovedY(event.value))),
             AxisName::StickX => Some(MouseEvent::Stick(PadStickEvent::MovedX(event.value))),
             AxisName::StickY => Some(MouseEvent::Stick(PadStickEvent::MovedY(event.value))),
+            AxisName::GyroPitch => Some(MouseEvent::Orientation(OrientationEvent::Pitch(
+                event.value,
+            ))),
+            AxisName::GyroRoll => {
+                Some(MouseEvent::Orientation(OrientationEvent::Roll(event.value)))
+            }
+            AxisName::GyroYaw => Some(MouseEvent::Orientation(OrientationEvent::Yaw(event.value))),
             _ => None,
         } {
             send_mouse_event(event_to_send)?;
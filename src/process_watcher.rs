@@ -0,0 +1,337 @@
+use crate::configs::{AppRulesConfigs, MainConfigs};
+use crate::key_codes::LayoutSwitch;
+use crate::process_event::LayoutSwitchSender;
+use crate::utils::{TerminationStatus, ThreadHandle};
+use color_eyre::eyre::Result;
+use log::{debug, warn};
+use std::fs::{read, read_dir, read_link, read_to_string};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::sleep;
+
+// Kernel truncates process names
+const COMM_MAX_LEN: usize = 15;
+
+#[derive(Clone, Debug, Default)]
+pub struct ProcessInfo {
+    pub comm: String,
+    pub exe: Option<PathBuf>,
+    // Wine and interpreters keep the real name only in the command line
+    pub argv0: Option<String>,
+}
+
+impl ProcessInfo {
+    fn read(process_dir: &Path) -> Option<Self> {
+        let comm = read_to_string(process_dir.join("comm")).ok()?;
+        let exe = read_link(process_dir.join("exe")).ok();
+        let argv0 = read(process_dir.join("cmdline")).ok().and_then(|cmdline| {
+            let argv0 = cmdline.split(|&byte| byte == 0).next()?;
+            match argv0.is_empty() {
+                true => None,
+                false => Some(String::from_utf8_lossy(argv0).to_string()),
+            }
+        });
+
+        Some(Self {
+            comm: comm.trim_end().to_string(),
+            exe,
+            argv0,
+        })
+    }
+
+    fn matches(&self, process: &str) -> bool {
+        // Path of the executable
+        if process.contains('/') {
+            return self.exe.as_deref() == Some(Path::new(process));
+        }
+
+        let is_same_name = |name: &str| name.eq_ignore_ascii_case(process);
+
+        // Longer names are cut in comm and can match unrelated processes
+        if process.chars().count() <= COMM_MAX_LEN && is_same_name(self.comm.as_str()) {
+            return true;
+        }
+
+        let exe_name = self
+            .exe
+            .as_ref()
+            .and_then(|exe| exe.file_name())
+            .and_then(|name| name.to_str());
+        if exe_name.is_some_and(is_same_name) {
+            return true;
+        }
+
+        // Windows paths under Wine use backslashes
+        let argv0_name = self
+            .argv0
+            .as_ref()
+            .and_then(|argv0| argv0.rsplit(['/', '\\']).next());
+        argv0_name.is_some_and(is_same_name)
+    }
+}
+
+pub fn running_processes(proc_root: &Path) -> Result<Vec<ProcessInfo>> {
+    let mut processes = vec![];
+    for entry in read_dir(proc_root)? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+        let is_pid = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.chars().all(|char| char.is_ascii_digit()));
+        if !is_pid {
+            continue;
+        }
+        // Process can exit while being read
+        if let Some(process) = ProcessInfo::read(entry.path().as_path()) {
+            processes.push(process);
+        }
+    }
+    Ok(processes)
+}
+
+pub struct ProcessWatcher {
+    app_rules_cfg: AppRulesConfigs,
+    default_layout_name: String,
+    active_layout_name: String,
+}
+
+impl ProcessWatcher {
+    pub fn new(app_rules_cfg: &AppRulesConfigs, default_layout_name: &str) -> Self {
+        Self {
+            app_rules_cfg: app_rules_cfg.clone(),
+            default_layout_name: default_layout_name.to_string(),
+            active_layout_name: default_layout_name.to_string(),
+        }
+    }
+
+    fn target_layout_name(&self, processes: &[ProcessInfo]) -> &str {
+        for rule in &self.app_rules_cfg.rules {
+            if processes
+                .iter()
+                .any(|process| process.matches(rule.process.as_str()))
+            {
+                return rule.buttons_layout_name.as_str();
+            }
+        }
+        self.default_layout_name.as_str()
+    }
+
+    // Returns the layout to activate only when the target changes,
+    // so manual switches stay until the next process starts or exits
+    pub fn check(&mut self) -> Result<Option<String>> {
+        let processes = running_processes(self.app_rules_cfg.proc_root.as_path())?;
+        let target_layout_name = self.target_layout_name(&processes);
+
+        if target_layout_name == self.active_layout_name {
+            return Ok(None);
+        }
+        self.active_layout_name = target_layout_name.to_string();
+        debug!("Process rules activate '{}'", self.active_layout_name);
+        Ok(Some(self.active_layout_name.clone()))
+    }
+}
+
+fn watch_processes(
+    mut process_watcher: ProcessWatcher,
+    layout_switch_sender: &LayoutSwitchSender,
+    stop_status: &TerminationStatus,
+) -> Result<()> {
+    let check_interval = process_watcher.app_rules_cfg.check_interval;
+
+    loop {
+        if stop_status.check() {
+            return Ok(());
+        }
+
+        match process_watcher.check() {
+            Ok(Some(layout_name)) => {
+                layout_switch_sender.send(LayoutSwitch::Named(layout_name))?;
+            }
+            Ok(None) => {}
+            Err(err) => warn!("Cannot scan processes: {}", err),
+        }
+
+        sleep(check_interval);
+    }
+}
+
+// One watcher per backend. Switches are sent to the given channel
+pub fn spawn_process_watcher(
+    layout_switch_sender: LayoutSwitchSender,
+    configs: &MainConfigs,
+    stop_status: &TerminationStatus,
+) -> Option<ThreadHandle> {
+    let app_rules_cfg = &configs.app_rules_cfg;
+    if app_rules_cfg.rules.is_empty() {
+        return None;
+    }
+
+    let process_watcher = ProcessWatcher::new(
+        app_rules_cfg,
+        configs.layout_names_cfg.buttons_layout_name.as_str(),
+    );
+    let stop_status = stop_status.clone();
+
+    Some(thread::spawn(move || {
+        // Fails only when the input pipeline is gone
+        if let Err(err) = watch_processes(process_watcher, &layout_switch_sender, &stop_status) {
+            debug!("Process watcher stopped: {}", err);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::os::unix::fs::symlink;
+
+    struct FakeProc(PathBuf);
+
+    impl FakeProc {
+        fn new(test_name: &str) -> Self {
+            let proc_root = temp_dir().join(format!(
+                "joystick-fake-proc-{}-{}",
+                test_name,
+                std::process::id()
+            ));
+            let _ = remove_dir_all(&proc_root);
+            create_dir_all(proc_root.join("self")).unwrap();
+            Self(proc_root)
+        }
+
+        fn add(&self, pid: u32, comm: &str, exe: Option<&str>, argv: &[&str]) -> PathBuf {
+            let process_dir = self.0.join(pid.to_string());
+            create_dir_all(&process_dir).unwrap();
+            write(process_dir.join("comm"), format!("{}\n", comm)).unwrap();
+            if let Some(exe) = exe {
+                symlink(exe, process_dir.join("exe")).unwrap();
+            }
+            let cmdline: Vec<u8> = argv.iter().flat_map(|arg| arg.bytes().chain([0])).collect();
+            write(process_dir.join("cmdline"), cmdline).unwrap();
+            process_dir
+        }
+
+        fn watcher(&self, rules: &str) -> ProcessWatcher {
+            let mut app_rules_cfg: AppRulesConfigs = serde_yml::from_str(
+                format!(
+                    "{{ proc_root: {:?}, check_interval_ms: 1000, rules: {} }}",
+                    self.0, rules
+                )
+                .as_str(),
+            )
+            .unwrap();
+            app_rules_cfg.load().unwrap();
+            ProcessWatcher::new(&app_rules_cfg, "desktop")
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+
+    const RULES: &str = r#"[
+        { process: "valheim.x86_64", buttons_layout: "valheim" },
+        { process: "/usr/bin/raft", buttons_layout: "raft" },
+        { process: "Risk.exe", buttons_layout: "risk" },
+    ]"#;
+
+    #[test]
+    fn rule_matches_by_name() -> Result<()> {
+        let fake_proc = FakeProc::new("name");
+        fake_proc.add(
+            1,
+            "systemd",
+            Some("/usr/lib/systemd/systemd"),
+            &["/sbin/init"],
+        );
+        let mut watcher = fake_proc.watcher(RULES);
+        assert_eq!(watcher.check()?, None);
+
+        fake_proc.add(
+            100,
+            "valheim.x86_64",
+            Some("/games/valheim/valheim.x86_64"),
+            &["./valheim.x86_64"],
+        );
+        assert_eq!(watcher.check()?.as_deref(), Some("valheim"));
+        // Reported only when the target changes
+        assert_eq!(watcher.check()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn rule_matches_by_exe_path() -> Result<()> {
+        let fake_proc = FakeProc::new("exe");
+        // Same name in another folder doesn't match the path
+        fake_proc.add(100, "raft", Some("/opt/raft"), &["/opt/raft"]);
+        let mut watcher = fake_proc.watcher(RULES);
+        assert_eq!(watcher.check()?, None);
+
+        fake_proc.add(200, "raft", Some("/usr/bin/raft"), &["raft"]);
+        assert_eq!(watcher.check()?.as_deref(), Some("raft"));
+        Ok(())
+    }
+
+    #[test]
+    fn rule_matches_wine_command_line() -> Result<()> {
+        let fake_proc = FakeProc::new("wine");
+        fake_proc.add(
+            100,
+            "wine64-preloade",
+            Some("/usr/bin/wine64-preloader"),
+            &["C:\\Games\\Risk.exe", "-windowed"],
+        );
+        let mut watcher = fake_proc.watcher(RULES);
+        assert_eq!(watcher.check()?.as_deref(), Some("risk"));
+        Ok(())
+    }
+
+    #[test]
+    fn default_layout_is_restored_after_exit() -> Result<()> {
+        let fake_proc = FakeProc::new("exit");
+        let process_dir = fake_proc.add(100, "valheim.x86_64", None, &[]);
+        let mut watcher = fake_proc.watcher(RULES);
+        assert_eq!(watcher.check()?.as_deref(), Some("valheim"));
+
+        remove_dir_all(process_dir)?;
+        assert_eq!(watcher.check()?.as_deref(), Some("desktop"));
+        assert_eq!(watcher.check()?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn vanished_processes_are_skipped() -> Result<()> {
+        let fake_proc = FakeProc::new("vanished");
+        fake_proc.add(100, "bash", Some("/usr/bin/bash"), &["bash"]);
+        // Exited between listing and reading: no files left
+        create_dir_all(fake_proc.0.join("200"))?;
+        // Not readable as text
+        let process_dir = fake_proc.0.join("300");
+        create_dir_all(&process_dir)?;
+        create_dir_all(process_dir.join("comm"))?;
+
+        let processes = running_processes(fake_proc.0.as_path())?;
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].comm, "bash");
+        assert_eq!(
+            processes[0].exe.as_deref(),
+            Some(Path::new("/usr/bin/bash"))
+        );
+        assert_eq!(processes[0].argv0.as_deref(), Some("bash"));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_proc_root_is_an_error() {
+        let fake_proc = FakeProc::new("missing");
+        let mut watcher = fake_proc.watcher(RULES);
+        remove_dir_all(&fake_proc.0).unwrap();
+        assert!(watcher.check().is_err());
+    }
+}
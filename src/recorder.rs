@@ -0,0 +1,123 @@
+use crate::configs::InputBackendName;
+use crate::match_event::TransformedEvent;
+use crate::process_event::ImplementationSpecificCfg;
+use color_eyre::eyre::{OptionExt, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{create_dir_all, read_to_string, File};
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+const EXTENSION: &str = "jsonl";
+
+// First line of a recording. Replay needs the same settings as the original backend
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordingHeader {
+    pub backend: InputBackendName,
+    pub layout_name: String,
+    pub impl_cfg: ImplementationSpecificCfg,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RecordedEvent {
+    // Time since the start of the recording
+    pub time_us: u64,
+    pub event: TransformedEvent,
+}
+
+#[derive(Debug)]
+pub struct EventRecorder {
+    writer: LineWriter<File>,
+    backend: InputBackendName,
+    layout_name: String,
+    is_header_written: bool,
+    start_time: Instant,
+}
+
+pub type SharedRecorder = Arc<Mutex<EventRecorder>>;
+
+fn new_recording_path(recording_dir: &Path, layout_name: &str) -> Result<PathBuf> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let base_name = format!("{}_{}", timestamp, layout_name);
+
+    let mut filepath = recording_dir.join(format!("{}.{}", base_name, EXTENSION));
+    let mut index = 1;
+    // Several gamepads can start recording at the same second
+    while filepath.exists() {
+        index += 1;
+        filepath = recording_dir.join(format!("{}_{}.{}", base_name, index, EXTENSION));
+    }
+    Ok(filepath)
+}
+
+impl EventRecorder {
+    pub fn create(
+        recording_dir: &Path,
+        backend: InputBackendName,
+        layout_name: &str,
+    ) -> Result<Self> {
+        create_dir_all(recording_dir)?;
+        let filepath = new_recording_path(recording_dir, layout_name)?;
+        println!("Recording events to '{}'", filepath.display());
+
+        Ok(Self {
+            writer: LineWriter::new(File::create(filepath)?),
+            backend,
+            layout_name: layout_name.to_string(),
+            is_header_written: false,
+            start_time: Instant::now(),
+        })
+    }
+
+    pub fn create_shared(
+        recording_dir: &Path,
+        backend: InputBackendName,
+        layout_name: &str,
+    ) -> Result<SharedRecorder> {
+        Ok(Arc::new(Mutex::new(Self::create(
+            recording_dir,
+            backend,
+            layout_name,
+        )?)))
+    }
+
+    pub fn record(
+        &mut self,
+        event: &TransformedEvent,
+        impl_cfg: &ImplementationSpecificCfg,
+    ) -> Result<()> {
+        if !self.is_header_written {
+            let header = RecordingHeader {
+                backend: self.backend,
+                layout_name: self.layout_name.clone(),
+                impl_cfg: *impl_cfg,
+            };
+            writeln!(self.writer, "{}", serde_json::to_string(&header)?)?;
+            self.is_header_written = true;
+        }
+
+        let recorded_event = RecordedEvent {
+            time_us: self.start_time.elapsed().as_micros() as u64,
+            event: event.clone(),
+        };
+        writeln!(self.writer, "{}", serde_json::to_string(&recorded_event)?)?;
+        Ok(())
+    }
+}
+
+pub fn read_recording<P: AsRef<Path>>(
+    filepath: P,
+) -> Result<(RecordingHeader, Vec<RecordedEvent>)> {
+    let content = read_to_string(filepath)?;
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+
+    let header: RecordingHeader =
+        serde_json::from_str(lines.next().ok_or_eyre("Recording is empty")?)?;
+
+    let mut events = vec![];
+    for line in lines {
+        events.push(serde_json::from_str(line)?);
+    }
+    Ok((header, events))
+}
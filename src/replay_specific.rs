@@ -0,0 +1,194 @@
+use crate::configs::{InputBackendName, MainConfigs};
+use crate::input_backend::InputBackend;
+use crate::match_event::TransformStatus;
+use crate::process_event::{process_event, SharedInfo};
+use crate::recorder::read_recording;
+use crate::utils::{check_thread_handle, TerminationStatus};
+use crate::writing_thread::spawn_writing_thread;
+use color_eyre::eyre::{bail, Result};
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+pub struct ReplayBackend {
+    filepath: PathBuf,
+    speed: f32,
+}
+
+impl ReplayBackend {
+    pub fn new(filepath: PathBuf, speed: f32) -> Result<Self> {
+        if speed < 0.0 {
+            bail!("Incorrect value for 'replay_speed': '{}'", speed);
+        }
+        Ok(Self { filepath, speed })
+    }
+}
+
+impl InputBackend for ReplayBackend {
+    fn name(&self) -> InputBackendName {
+        InputBackendName::Replay
+    }
+
+    fn is_available(&mut self) -> bool {
+        self.filepath.exists()
+    }
+
+    fn run(&mut self, configs: &MainConfigs, termination_status: &TerminationStatus) -> Result<()> {
+        let (header, events) = read_recording(&self.filepath)?;
+
+        // Behave as the backend that produced the recording
+        let mut configs = configs.with_layout(header.layout_name.as_str())?;
+        configs.backend = header.backend;
+        configs.recording_dir = None;
+
+        let mut shared_info = SharedInfo::new(&configs)?;
+        let writing_thread = spawn_writing_thread(&shared_info, &configs, termination_status);
+
+        println!(
+            "Replaying {} events from '{}'",
+            events.len(),
+            self.filepath.display()
+        );
+
+        let start_time = Instant::now();
+        for recorded_event in events {
+            if termination_status.check() {
+                return Ok(());
+            };
+            check_thread_handle(Some(&writing_thread))?;
+
+            if self.speed > 0.0 {
+                let event_time = Duration::from_micros(recorded_event.time_us).div_f32(self.speed);
+                if let Some(remaining) = event_time.checked_sub(start_time.elapsed()) {
+                    sleep(remaining);
+                }
+            }
+
+            // Switch codes in the recording change the layout as in the live backends
+            shared_info.update_layout()?;
+
+            process_event(
+                TransformStatus::Transformed(recorded_event.event),
+                &shared_info,
+                &header.impl_cfg,
+            )?;
+        }
+
+        shared_info.release_all_hard()?;
+
+        // Let the writing thread handle the remaining events
+        let writing_interval = configs.general.mouse_refresh_interval;
+        while !(shared_info.mouse_receiver.is_empty() && shared_info.button_receiver.is_empty()) {
+            // Nothing drains the channels once the writing thread stops
+            if termination_status.check() {
+                return Ok(());
+            };
+            check_thread_handle(Some(&writing_thread))?;
+            sleep(writing_interval);
+        }
+        sleep(writing_interval);
+
+        println!("Replay finished");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::match_event::{AxisName, ButtonName, EventTypeName, TransformedEvent};
+    use crate::process_event::ButtonEvent::{Pressed, Released};
+    use crate::process_event::{ButtonEvent, ImplementationSpecificCfg};
+    use color_eyre::eyre::OptionExt;
+    use std::env::temp_dir;
+    use std::fs::{read_dir, remove_dir_all};
+    use std::path::Path;
+
+    fn button_event(event_type: EventTypeName, button: ButtonName) -> TransformedEvent {
+        TransformedEvent {
+            event_type,
+            axis: AxisName::None,
+            button,
+            value: 0.0,
+        }
+    }
+
+    fn trigger_event(value: f32) -> TransformedEvent {
+        TransformedEvent {
+            event_type: EventTypeName::AxisChanged,
+            axis: AxisName::LowerTrigger_SideR,
+            button: ButtonName::None,
+            value,
+        }
+    }
+
+    fn button_events(shared_info: &SharedInfo) -> Vec<ButtonEvent> {
+        shared_info.button_receiver.try_iter().collect()
+    }
+
+    #[test]
+    fn recording_replays_the_same_events() -> Result<()> {
+        let recording_dir = temp_dir().join(format!("joystick-replay-{}", std::process::id()));
+        let mut configs = MainConfigs::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("config"))?;
+        configs.backend = InputBackendName::Gilrs;
+        configs.recording_dir = Some(recording_dir.clone());
+        // Trigger range of gilrs. Replay has to convert with it, not with its own
+        let impl_cfg = ImplementationSpecificCfg::new(-1.0, 1.0, true);
+
+        let events = [
+            button_event(EventTypeName::ButtonPressed, ButtonName::BtnDown_SideR),
+            trigger_event(0.0),
+            button_event(EventTypeName::ButtonReleased, ButtonName::BtnDown_SideR),
+            trigger_event(-1.0),
+        ];
+        let recorded = {
+            let shared_info = SharedInfo::new(&configs)?;
+            for event in events.clone() {
+                process_event(TransformStatus::Transformed(event), &shared_info, &impl_cfg)?;
+            }
+            button_events(&shared_info)
+        };
+        assert_eq!(
+            recorded,
+            [
+                Pressed(ButtonName::BtnDown_SideR),
+                Pressed(ButtonName::LowerTriggerAsBtn_SideR),
+                Released(ButtonName::BtnDown_SideR),
+                Released(ButtonName::LowerTriggerAsBtn_SideR),
+            ]
+        );
+
+        let filepath = read_dir(&recording_dir)?
+            .next()
+            .ok_or_eyre("Recording is not written")??
+            .path();
+        let result = read_recording(filepath);
+        remove_dir_all(&recording_dir)?;
+        let (header, recorded_events) = result?;
+
+        assert_eq!(header.backend, InputBackendName::Gilrs);
+        assert_eq!(
+            header.layout_name,
+            configs.layout_names_cfg.buttons_layout_name
+        );
+        assert_eq!(recorded_events.len(), events.len());
+        for (recorded_event, event) in recorded_events.iter().zip(&events) {
+            assert_eq!(recorded_event.event.event_type, event.event_type);
+            assert_eq!(recorded_event.event.axis, event.axis);
+            assert_eq!(recorded_event.event.button, event.button);
+            assert_eq!(recorded_event.event.value, event.value);
+        }
+
+        configs.recording_dir = None;
+        let shared_info = SharedInfo::new(&configs)?;
+        for recorded_event in recorded_events {
+            process_event(
+                TransformStatus::Transformed(recorded_event.event),
+                &shared_info,
+                &header.impl_cfg,
+            )?;
+        }
+        assert_eq!(button_events(&shared_info), recorded);
+        Ok(())
+    }
+}
@@ -0,0 +1,625 @@
+use crate::layout_import::{is_wasd, ImportedLayout};
+use crate::match_event::ButtonName;
+use color_eyre::eyre::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use universal_input::KeyCode;
+
+// Wrappers that only tune the inner action
+const MODIFIERS: [&str; 7] = [
+    "sens", "feedback", "smooth", "deadzone", "ball", "rotate", "click",
+];
+const MOUSE_RELS: [&str; 2] = ["Rels.REL_X", "Rels.REL_Y"];
+const WHEEL_RELS: [&str; 2] = ["Rels.REL_WHEEL", "Rels.REL_HWHEEL"];
+
+// SC-Controller action expression, e.g. "dpad(button(Keys.KEY_W), button(Keys.KEY_S), ...)"
+#[derive(Clone, Debug, PartialEq)]
+enum SccAction {
+    Call(String, Vec<SccAction>),
+    // Constants like 'Keys.KEY_A' and literals
+    Name(String),
+}
+
+impl fmt::Display for SccAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SccAction::Name(name) => write!(f, "{}", name),
+            SccAction::Call(name, args) if name == "and" => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}", args.join(" and "))
+            }
+            SccAction::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+impl SccAction {
+    fn name(&self) -> &str {
+        match self {
+            SccAction::Call(name, _) | SccAction::Name(name) => name.as_str(),
+        }
+    }
+
+    fn args(&self) -> &[SccAction] {
+        match self {
+            SccAction::Call(_, args) => args,
+            SccAction::Name(_) => &[],
+        }
+    }
+
+    fn unwrap_modifiers(&self) -> &Self {
+        match self {
+            SccAction::Call(name, args) if MODIFIERS.contains(&name.as_str()) => {
+                match args.last() {
+                    Some(inner) => inner.unwrap_modifiers(),
+                    None => self,
+                }
+            }
+            _ => self,
+        }
+    }
+
+    fn is_mouse(&self) -> bool {
+        match self.name() {
+            "mouse" | "trackpad" | "trackball" => self
+                .args()
+                .first()
+                .is_none_or(|axis| MOUSE_RELS.contains(&axis.name())),
+            "XY" => self
+                .args()
+                .iter()
+                .all(|arg| arg.unwrap_modifiers().is_mouse()),
+            _ => false,
+        }
+    }
+
+    fn is_scroll(&self) -> bool {
+        match self.name() {
+            "mouse" => self
+                .args()
+                .first()
+                .is_some_and(|axis| WHEEL_RELS.contains(&axis.name())),
+            "XY" => self
+                .args()
+                .iter()
+                .all(|arg| arg.unwrap_modifiers().is_scroll()),
+            _ => false,
+        }
+    }
+}
+
+struct ActionParser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl ActionParser {
+    fn tokenize(action: &str) -> Vec<String> {
+        let mut tokens = vec![];
+        let mut chars = action.chars().peekable();
+        while let Some(char) = chars.next() {
+            match char {
+                '(' | ')' | ',' => tokens.push(char.to_string()),
+                '\'' | '"' => {
+                    let mut text = String::new();
+                    for next in chars.by_ref() {
+                        if next == char {
+                            break;
+                        }
+                        text.push(next);
+                    }
+                    tokens.push(text);
+                }
+                char if char.is_whitespace() => {}
+                char => {
+                    let mut text = char.to_string();
+                    while let Some(&next) = chars.peek() {
+                        if next.is_whitespace() || "(),".contains(next) {
+                            break;
+                        }
+                        text.push(next);
+                        chars.next();
+                    }
+                    tokens.push(text);
+                }
+            }
+        }
+        tokens
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => bail!("'{}' is expected, got {:?}", expected, token),
+        }
+    }
+
+    // Actions joined with 'and' are pressed together
+    fn expression(&mut self) -> Result<SccAction> {
+        let mut actions = vec![self.term()?];
+        while self.peek() == Some("and") {
+            self.next();
+            actions.push(self.term()?);
+        }
+        match actions.len() {
+            1 => Ok(actions.remove(0)),
+            _ => Ok(SccAction::Call("and".to_string(), actions)),
+        }
+    }
+
+    fn term(&mut self) -> Result<SccAction> {
+        let Some(name) = self.next() else {
+            bail!("Action is incomplete")
+        };
+        if self.peek() != Some("(") {
+            return Ok(SccAction::Name(name));
+        }
+        self.expect("(")?;
+
+        let mut args = vec![];
+        if self.peek() == Some(")") {
+            self.next();
+            return Ok(SccAction::Call(name, args));
+        }
+        loop {
+            args.push(self.expression()?);
+            match self.next().as_deref() {
+                Some(",") => {}
+                Some(")") => return Ok(SccAction::Call(name, args)),
+                token => bail!("',' or ')' is expected in '{}', got {:?}", name, token),
+            }
+        }
+    }
+
+    fn parse(action: &str) -> Result<SccAction> {
+        let mut parser = Self {
+            tokens: Self::tokenize(action),
+            position: 0,
+        };
+        let action = parser.expression()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected '{}'", token)
+        }
+        Ok(action)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct SccEntry {
+    #[serde(default)]
+    action: Option<String>,
+    // Mode shifts
+    #[serde(default)]
+    modes: Option<serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct SccProfile {
+    #[serde(default)]
+    buttons: BTreeMap<String, SccEntry>,
+    #[serde(default)]
+    pad_left: SccEntry,
+    #[serde(default)]
+    pad_right: SccEntry,
+    #[serde(default)]
+    stick: SccEntry,
+    #[serde(default)]
+    trigger_left: SccEntry,
+    #[serde(default)]
+    trigger_right: SccEntry,
+    #[serde(default)]
+    gyro: SccEntry,
+}
+
+fn button_from_scc(name: &str) -> Option<ButtonName> {
+    let button = match name {
+        "A" => ButtonName::BtnDown_SideR,
+        "B" => ButtonName::BtnRight_SideR,
+        "X" => ButtonName::BtnLeft_SideR,
+        "Y" => ButtonName::BtnUp_SideR,
+        "LB" => ButtonName::UpperTrigger_SideL,
+        "RB" => ButtonName::UpperTrigger_SideR,
+        "LGRIP" => ButtonName::Wing_SideL,
+        "RGRIP" => ButtonName::Wing_SideR,
+        "BACK" => ButtonName::ExtraBtn_SideL,
+        "C" => ButtonName::ExtraBtnCentral,
+        "START" => ButtonName::ExtraBtn_SideR,
+        "STICKPRESS" => ButtonName::StickAsBtn,
+        "LPAD" | "LPADPRESS" => ButtonName::PadAsBtn_SideL,
+        "RPAD" | "RPADPRESS" => ButtonName::PadAsBtn_SideR,
+        _ => return None,
+    };
+    Some(button)
+}
+
+// Directions in order: up, right, down, left
+fn dpad_buttons(side: &str) -> Option<[ButtonName; 4]> {
+    let buttons = match side {
+        "pad_left" => [
+            ButtonName::PadUp_SideL,
+            ButtonName::PadRight_SideL,
+            ButtonName::PadDown_SideL,
+            ButtonName::PadLeft_SideL,
+        ],
+        "pad_right" => [
+            ButtonName::PadUp_SideR,
+            ButtonName::PadRight_SideR,
+            ButtonName::PadDown_SideR,
+            ButtonName::PadLeft_SideR,
+        ],
+        // Stick zones press the codes of these buttons
+        "stick" => [
+            ButtonName::BtnUp_SideL,
+            ButtonName::BtnRight_SideL,
+            ButtonName::BtnDown_SideL,
+            ButtonName::BtnLeft_SideL,
+        ],
+        _ => return None,
+    };
+    Some(buttons)
+}
+
+fn key_code_from_scc(name: &str) -> Option<String> {
+    let name = name.strip_prefix("Keys.")?;
+    let code = match name {
+        "BTN_LEFT" => "MOUSE_LEFT".to_string(),
+        "BTN_RIGHT" => "MOUSE_RIGHT".to_string(),
+        "BTN_MIDDLE" => "MOUSE_MIDDLE".to_string(),
+        name => name.to_string(),
+    };
+    KeyCode::try_from(code.as_str()).ok()?;
+    Some(code)
+}
+
+fn action_codes(imported: &mut ImportedLayout, context: &str, action: &SccAction) -> Vec<String> {
+    let action = action.unwrap_modifiers();
+    match action.name() {
+        "and" => action
+            .args()
+            .iter()
+            .flat_map(|action| action_codes(imported, context, action))
+            .collect(),
+        "button" => match action
+            .args()
+            .first()
+            .and_then(|key| key_code_from_scc(key.name()))
+        {
+            Some(code) => vec![code],
+            None => {
+                imported.skip(format!("'{}': '{}'", context, action));
+                vec![]
+            }
+        },
+        // Wheel by one step
+        "mouse" if action.is_scroll() => {
+            let is_negative = action
+                .args()
+                .get(1)
+                .is_some_and(|amount| amount.name().starts_with('-'));
+            match is_negative {
+                false => vec!["KEY_SCROLLUP".to_string()],
+                true => vec!["KEY_SCROLLDOWN".to_string()],
+            }
+        }
+        "None" => vec![],
+        _ => {
+            imported.skip(format!("'{}': '{}'", context, action));
+            vec![]
+        }
+    }
+}
+
+fn parse_entry(
+    imported: &mut ImportedLayout,
+    context: &str,
+    entry: &SccEntry,
+) -> Option<SccAction> {
+    if entry.modes.is_some() {
+        imported.skip(format!("'{}': mode shift", context));
+    }
+    let action = entry.action.as_ref()?;
+    match ActionParser::parse(action) {
+        Ok(action) => Some(action),
+        Err(err) => {
+            imported.skip(format!("'{}': cannot parse '{}': {}", context, action, err));
+            None
+        }
+    }
+}
+
+fn import_dpad(imported: &mut ImportedLayout, side: &str, action: &SccAction) {
+    let args = action.args();
+    if args.len() > 4 {
+        imported.skip(format!("'{}': diagonals of '{}'", side, action.name()));
+    }
+    // Order in SC-Controller: up, down, left, right
+    let [up, down, left, right] = [0, 1, 2, 3].map(|ind| match args.get(ind) {
+        Some(arg) => action_codes(imported, side, arg),
+        None => vec![],
+    });
+    let dpad_codes = [up, right, down, left];
+
+    if side == "pad_left" && is_wasd(&dpad_codes) {
+        imported.gaming_mode = Some(true);
+        return;
+    }
+    let Some(buttons) = dpad_buttons(side) else {
+        return;
+    };
+    for (button, codes) in buttons.into_iter().zip(dpad_codes) {
+        if !codes.is_empty() {
+            imported.buttons.push((button, codes));
+        }
+    }
+}
+
+fn import_pad(imported: &mut ImportedLayout, side: &str, entry: &SccEntry) {
+    let Some(action) = parse_entry(imported, side, entry) else {
+        return;
+    };
+    let action = action.unwrap_modifiers();
+
+    match (side, action.name()) {
+        (_, "None") => {}
+        (_, "dpad" | "dpad8") => import_dpad(imported, side, action),
+        // Right pad always moves the mouse
+        ("pad_right", _) if action.is_mouse() => {}
+        ("pad_left", _) if action.is_scroll() => imported.gaming_mode = Some(false),
+        _ => imported.skip(format!("'{}': '{}'", side, action)),
+    }
+}
+
+fn import_trigger(imported: &mut ImportedLayout, side: &str, button: ButtonName, entry: &SccEntry) {
+    let Some(action) = parse_entry(imported, side, entry) else {
+        return;
+    };
+    let action = action.unwrap_modifiers();
+
+    // trigger(press_level, release_level, action)
+    let action = match action.name() {
+        "trigger" => match action.args().last() {
+            Some(action) => action,
+            None => return,
+        },
+        _ => action,
+    };
+    let codes = action_codes(imported, side, action);
+    if !codes.is_empty() {
+        imported.buttons.push((button, codes));
+    }
+}
+
+pub fn import_sccprofile(content: &str) -> Result<ImportedLayout> {
+    let profile: SccProfile = serde_json::from_str(content)?;
+    let mut imported = ImportedLayout::new("SC-Controller profile");
+
+    for (name, entry) in &profile.buttons {
+        let context = format!("buttons.{}", name);
+        let Some(action) = parse_entry(&mut imported, context.as_str(), entry) else {
+            continue;
+        };
+        let Some(button) = button_from_scc(name) else {
+            imported.skip(format!("'{}'", context));
+            continue;
+        };
+        let codes = action_codes(&mut imported, context.as_str(), &action);
+        if !codes.is_empty() {
+            imported.buttons.push((button, codes));
+        }
+    }
+
+    import_pad(&mut imported, "pad_left", &profile.pad_left);
+    import_pad(&mut imported, "pad_right", &profile.pad_right);
+    import_pad(&mut imported, "stick", &profile.stick);
+    import_trigger(
+        &mut imported,
+        "trigger_left",
+        ButtonName::LowerTriggerAsBtn_SideL,
+        &profile.trigger_left,
+    );
+    import_trigger(
+        &mut imported,
+        "trigger_right",
+        ButtonName::LowerTriggerAsBtn_SideR,
+        &profile.trigger_right,
+    );
+    if profile.gyro.action.is_some() {
+        imported.skip("'gyro'".to_string());
+    }
+
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configs::LayoutConfigs;
+    use crate::file_ops::yaml_filepath;
+    use std::env::temp_dir;
+    use std::fs::{copy, create_dir_all, remove_dir_all, write};
+    use std::path::Path;
+
+    const SCC_PROFILE: &str = r#"
+{
+    "buttons": {
+        "A": { "action": "button(Keys.KEY_SPACE)" },
+        "B": { "action": "button(Keys.KEY_LEFTCTRL) and button(Keys.KEY_C)" },
+        "LGRIP": { "action": "button(Keys.BTN_RIGHT)" },
+        "X": { "action": "hold(button(Keys.KEY_R), button(Keys.KEY_E))" },
+        "Y": { "action": "button(Keys.KEY_Y" }
+    },
+    "pad_left": {
+        "action": "dpad(button(Keys.KEY_W), button(Keys.KEY_S), button(Keys.KEY_A), button(Keys.KEY_D))"
+    },
+    "pad_right": { "action": "smooth(8, 0.78, 2.0, ball(mouse()))" },
+    "trigger_right": { "action": "trigger(254, 255, button(Keys.KEY_F))" },
+    "gyro": { "action": "gyro(Rels.REL_Y, None, Rels.REL_X)" }
+}
+"#;
+
+    fn call(name: &str, args: Vec<SccAction>) -> SccAction {
+        SccAction::Call(name.to_string(), args)
+    }
+
+    fn name(name: &str) -> SccAction {
+        SccAction::Name(name.to_string())
+    }
+
+    #[test]
+    fn button_is_parsed() -> Result<()> {
+        assert_eq!(
+            ActionParser::parse("button(Keys.KEY_A)")?,
+            call("button", vec![name("Keys.KEY_A")])
+        );
+        assert_eq!(
+            ActionParser::parse("button(Keys.KEY_LEFTCTRL) and button(Keys.KEY_C)")?,
+            call(
+                "and",
+                vec![
+                    call("button", vec![name("Keys.KEY_LEFTCTRL")]),
+                    call("button", vec![name("Keys.KEY_C")]),
+                ]
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn nested_actions_are_parsed() -> Result<()> {
+        let action = ActionParser::parse(
+            "hold(button(Keys.KEY_R), dpad(button(Keys.KEY_W), None, 'Menu item'), mouse())",
+        )?;
+        assert_eq!(
+            action,
+            call(
+                "hold",
+                vec![
+                    call("button", vec![name("Keys.KEY_R")]),
+                    call(
+                        "dpad",
+                        vec![
+                            call("button", vec![name("Keys.KEY_W")]),
+                            name("None"),
+                            name("Menu item"),
+                        ]
+                    ),
+                    call("mouse", vec![]),
+                ]
+            )
+        );
+        assert_eq!(
+            action.to_string(),
+            "hold(button(Keys.KEY_R), dpad(button(Keys.KEY_W), None, Menu item), mouse())"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn modifiers_are_unwrapped() -> Result<()> {
+        let action = ActionParser::parse("smooth(8, 0.78, 2.0, ball(mouse()))")?;
+        assert_eq!(action.unwrap_modifiers(), &call("mouse", vec![]));
+        assert!(action.unwrap_modifiers().is_mouse());
+
+        let action = ActionParser::parse("mouse(Rels.REL_WHEEL, -1)")?;
+        assert!(action.is_scroll());
+        assert!(!action.is_mouse());
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_actions_are_errors() {
+        let error = |action: &str| match ActionParser::parse(action) {
+            Ok(action) => panic!("'{}' is parsed", action),
+            Err(err) => err.to_string(),
+        };
+        assert_eq!(error(""), "Action is incomplete");
+        assert_eq!(
+            error("button(Keys.KEY_A"),
+            "',' or ')' is expected in 'button', got None"
+        );
+        assert_eq!(
+            error("button(Keys.KEY_A Keys.KEY_B)"),
+            "',' or ')' is expected in 'button', got Some(\"Keys.KEY_B\")"
+        );
+        assert_eq!(error("button(Keys.KEY_A))"), "Unexpected ')'");
+        assert_eq!(error("button(Keys.KEY_A) and"), "Action is incomplete");
+    }
+
+    #[test]
+    fn profile_is_imported() -> Result<()> {
+        let imported = import_sccprofile(SCC_PROFILE)?;
+
+        assert_eq!(imported.gaming_mode, Some(true));
+        assert_eq!(
+            imported.buttons,
+            [
+                (ButtonName::BtnDown_SideR, vec!["KEY_SPACE".to_string()]),
+                (
+                    ButtonName::BtnRight_SideR,
+                    vec!["KEY_LEFTCTRL".to_string(), "KEY_C".to_string()]
+                ),
+                (ButtonName::Wing_SideL, vec!["MOUSE_RIGHT".to_string()]),
+                (
+                    ButtonName::LowerTriggerAsBtn_SideR,
+                    vec!["KEY_F".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(
+            imported.skipped,
+            [
+                "'buttons.X': 'hold(button(Keys.KEY_R), button(Keys.KEY_E))'",
+                "'buttons.Y': cannot parse 'button(Keys.KEY_Y': ',' or ')' is expected in 'button', got None",
+                "'gyro'",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn imported_layout_loads() -> Result<()> {
+        let layouts_dir = temp_dir().join(format!("joystick-scc-import-{}", std::process::id()));
+        create_dir_all(&layouts_dir)?;
+        let repo_layouts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/layouts");
+        copy(
+            yaml_filepath(&repo_layouts_dir, "desktop"),
+            yaml_filepath(&layouts_dir, "desktop"),
+        )?;
+        let layout_yaml = import_sccprofile(SCC_PROFILE)?.to_layout_yaml("desktop")?;
+        write(yaml_filepath(&layouts_dir, "imported"), layout_yaml)?;
+
+        let result = LayoutConfigs::load("imported", &layouts_dir);
+        remove_dir_all(&layouts_dir)?;
+        let layout_configs = result?;
+
+        assert!(layout_configs.general.gaming_mode);
+        let layout = &layout_configs.buttons_layout.layout;
+        assert_eq!(layout[&ButtonName::BtnDown_SideR][..], [KeyCode::KEY_SPACE]);
+        assert_eq!(
+            layout[&ButtonName::BtnRight_SideR][..],
+            [KeyCode::KEY_LEFTCTRL, KeyCode::KEY_C]
+        );
+        assert_eq!(layout[&ButtonName::Wing_SideL][..], [KeyCode::MOUSE_RIGHT]);
+        assert_eq!(
+            layout[&ButtonName::LowerTriggerAsBtn_SideR][..],
+            [KeyCode::KEY_F]
+        );
+        // Not in the profile, so it comes from the base layout
+        assert_eq!(layout[&ButtonName::Wing_SideR][..], [KeyCode::KEY_ENTER]);
+        Ok(())
+    }
+}
@@ -1,9 +1,15 @@
-use crate::configs::MainConfigs;
+use crate::configs::{InputBackendName, MainConfigs};
+use crate::input_backend::InputBackend;
 use crate::match_event::{AxisName, ButtonName, EventTypeName, TransformStatus, TransformedEvent};
+use crate::math_ops::{coord_to_f32, Vector3};
+use crate::motion_gestures::GestureDetector;
+use crate::motion_sensor::{controller_id, MotionSensor};
 use crate::process_event::{process_event, ImplementationSpecificCfg, SharedInfo};
+use crate::process_watcher::spawn_process_watcher;
 use crate::steamy_event::{SteamyButton, SteamyEvent, SteamyPadStickF32, SteamyTrigger};
 use crate::steamy_state::SteamyState;
-use crate::utils::{create_channel, TerminationStatus};
+use crate::utils::{check_thread_handle, create_channel, TerminationStatus, ThreadHandleOption};
+use crate::writing_thread::{spawn_writing_thread, write_events};
 use color_eyre::eyre::{bail, Result};
 use log::{debug, error, warn};
 use std::fs::File;
@@ -18,6 +24,8 @@ use crossbeam_channel::{Receiver, Sender};
 use kanal::{Receiver, Sender};
 use steamy_base::Manager;
 
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(5000);
+
 pub type SteamyEventSender = Sender<SteamyEvent>;
 pub type SteamyEventReceiver = Receiver<SteamyEvent>;
 
@@ -135,6 +143,28 @@ pub fn normalize_event(event: &SteamyEvent, RESET_BTN: ButtonName) -> Result<Tra
     })
 }
 
+pub fn angles_to_vector(angles: &steamy_base::Angles) -> Vector3 {
+    Vector3 {
+        x: coord_to_f32(angles.pitch),
+        y: coord_to_f32(angles.roll),
+        z: coord_to_f32(angles.yaw),
+    }
+}
+
+pub fn normalize_orientation(orientation: Vector3) -> [TransformedEvent; 3] {
+    [
+        (AxisName::GyroPitch, orientation.x),
+        (AxisName::GyroRoll, orientation.y),
+        (AxisName::GyroYaw, orientation.z),
+    ]
+    .map(|(axis, value)| TransformedEvent {
+        event_type: EventTypeName::AxisChanged,
+        axis,
+        value,
+        button: ButtonName::None,
+    })
+}
+
 #[inline(always)]
 fn read_events(
     controller: &mut steamy_base::Controller,
@@ -216,7 +246,7 @@ fn read_events_loop(
     controller: &mut steamy_base::Controller,
     configs: &MainConfigs,
     steam_event_sender: &SteamyEventSender,
-    termination_status: &TerminationStatus,
+    stop_status: &TerminationStatus,
 ) -> Result<()> {
     // let steamy_read_interrupt_interval = configs.general.steamy_read_interrupt_interval;
     let input_raw_refresh_interval = configs.general.input_raw_refresh_interval;
@@ -236,11 +266,11 @@ fn read_events_loop(
     loop {
         let loop_start_time = Instant::now();
 
-        if termination_status.check() {
+        if stop_status.check() {
             return Ok(());
         };
 
-        let received_events = read_events(
+        let received_events = match read_events(
             controller,
             configs,
             steam_event_sender,
@@ -250,7 +280,15 @@ fn read_events_loop(
             &mut subject_endings_file,
             &mut cmp_file,
             &mut msg_counter,
-        )?;
+        ) {
+            Ok(received_events) => received_events,
+            Err(err) => {
+                // Cable or dongle is unplugged
+                warn!("Cannot read the gamepad: {}", err);
+                steam_event_sender.send(SteamyEvent::Disconnected)?;
+                return Ok(());
+            }
+        };
 
         let loop_iteration_runtime = loop_start_time.elapsed();
 
@@ -260,23 +298,53 @@ fn read_events_loop(
     }
 }
 
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+enum LoopExit {
+    Terminated,
+    Disconnected,
+}
+
 #[inline(always)]
 fn process_events(
     shared_info: &SharedInfo,
     impl_cfg: &ImplementationSpecificCfg,
     steam_event_receiver: &SteamyEventReceiver,
+    motion_sensor: &mut MotionSensor,
+    gesture_detector: &mut GestureDetector,
 ) -> Result<bool> {
     for event in steam_event_receiver.try_iter() {
         // while let Some(event) = steam_event_receiver.try_recv()? {
-        let is_disconnected = event == SteamyEvent::Disconnected;
-
-        let event = normalize_event(&event, shared_info.RESET_BTN)?;
-        process_event(event, shared_info, &impl_cfg)?;
-
-        if is_disconnected {
-            shared_info.release_all_hard()?;
-            println!("Gamepad disconnected");
-            return Ok(true);
+        match event {
+            SteamyEvent::Orientation(orientation) => {
+                let orientation = angles_to_vector(&orientation);
+                if let Some(orientation) =
+                    motion_sensor.correct_orientation(orientation, Instant::now())?
+                {
+                    for event in normalize_orientation(orientation) {
+                        process_event(TransformStatus::Transformed(event), shared_info, &impl_cfg)?;
+                    }
+                }
+            }
+            SteamyEvent::Acceleration(acceleration) => {
+                let acceleration = angles_to_vector(&acceleration);
+                motion_sensor.update_acceleration(acceleration, Instant::now())?;
+                for event in gesture_detector.update(acceleration, Instant::now()) {
+                    process_event(TransformStatus::Transformed(event), shared_info, &impl_cfg)?;
+                }
+            }
+            SteamyEvent::Connected => {
+                println!("Gamepad is on");
+            }
+            SteamyEvent::Disconnected => {
+                // Keep the layout and the mode for reconnection
+                shared_info.reset_controller_state()?;
+                println!("Gamepad disconnected");
+                return Ok(true);
+            }
+            _ => {
+                let event = normalize_event(&event, shared_info.RESET_BTN)?;
+                process_event(event, shared_info, &impl_cfg)?;
+            }
         }
     }
 
@@ -285,24 +353,40 @@ fn process_events(
 
 #[cfg(feature = "steamy_use_threads")]
 fn process_event_loop(
-    shared_info: &SharedInfo,
+    shared_info: &mut SharedInfo,
     configs: &MainConfigs,
     steam_event_receiver: &SteamyEventReceiver,
     termination_status: &TerminationStatus,
-) -> Result<()> {
-    let impl_cfg = ImplementationSpecificCfg::new(0.0, 1.0);
+    process_watcher: ThreadHandleOption,
+    controller_id: &str,
+) -> Result<LoopExit> {
+    let impl_cfg = ImplementationSpecificCfg::new(0.0, 1.0, false);
+    let mut motion_sensor = MotionSensor::new(configs, controller_id)?;
+    let mut gesture_detector = GestureDetector::new(&shared_info.layout_configs.gestures_cfg);
     let input_buffer_refresh_interval = configs.general.input_buffer_refresh_interval;
 
     loop {
         let loop_start_time = Instant::now();
 
         if termination_status.check() {
-            return Ok(());
+            return Ok(LoopExit::Terminated);
         };
 
-        let exit_now = process_events(shared_info, &impl_cfg, steam_event_receiver)?;
-        if exit_now {
-            return Ok(());
+        check_thread_handle(process_watcher)?;
+        if shared_info.update_layout()? {
+            gesture_detector = GestureDetector::new(&shared_info.layout_configs.gestures_cfg);
+            motion_sensor.update_layout(&shared_info.layout_configs)?;
+        }
+
+        let is_disconnected = process_events(
+            shared_info,
+            &impl_cfg,
+            steam_event_receiver,
+            &mut motion_sensor,
+            &mut gesture_detector,
+        )?;
+        if is_disconnected {
+            return Ok(LoopExit::Disconnected);
         }
 
         let loop_iteration_runtime = loop_start_time.elapsed();
@@ -316,13 +400,17 @@ fn process_event_loop(
 #[cfg(not(feature = "steamy_use_threads"))]
 fn process_event_loop(
     controller: &mut steamy_base::Controller,
-    shared_info: &SharedInfo,
+    shared_info: &mut SharedInfo,
     configs: &MainConfigs,
     steam_event_sender: &SteamyEventSender,
     steam_event_receiver: &SteamyEventReceiver,
     termination_status: &TerminationStatus,
-) -> Result<()> {
-    let impl_cfg = ImplementationSpecificCfg::new(0.0, 1.0);
+    process_watcher: ThreadHandleOption,
+    controller_id: &str,
+) -> Result<LoopExit> {
+    let impl_cfg = ImplementationSpecificCfg::new(0.0, 1.0, false);
+    let mut motion_sensor = MotionSensor::new(configs, controller_id)?;
+    let mut gesture_detector = GestureDetector::new(&shared_info.layout_configs.gestures_cfg);
 
     let input_buffer_refresh_interval = configs.general.input_buffer_refresh_interval;
     let input_raw_refresh_interval = configs.general.input_raw_refresh_interval;
@@ -345,10 +433,10 @@ fn process_event_loop(
         let read_loop_start_time = Instant::now();
 
         if termination_status.check() {
-            return Ok(());
+            return Ok(LoopExit::Terminated);
         };
 
-        let received_events = read_events(
+        let received_events = match read_events(
             controller,
             configs,
             steam_event_sender,
@@ -358,7 +446,16 @@ fn process_event_loop(
             &mut subject_endings_file,
             &mut cmp_file,
             &mut msg_counter,
-        )?;
+        ) {
+            Ok(received_events) => received_events,
+            Err(err) => {
+                // Cable or dongle is unplugged
+                warn!("Cannot read the gamepad: {}", err);
+                shared_info.reset_controller_state()?;
+                println!("Gamepad disconnected");
+                return Ok(LoopExit::Disconnected);
+            }
+        };
         // events_buffer.extend(received_events);
 
         let process_loop_iteration_runtime = process_loop_start_time.elapsed();
@@ -366,9 +463,21 @@ fn process_event_loop(
         if input_buffer_refresh_interval.checked_sub(process_loop_iteration_runtime) == None {
             process_loop_start_time = Instant::now();
 
-            let exit_now = process_events(shared_info, &impl_cfg, steam_event_receiver)?;
-            if exit_now {
-                return Ok(());
+            check_thread_handle(process_watcher)?;
+            if shared_info.update_layout()? {
+                gesture_detector = GestureDetector::new(&shared_info.layout_configs.gestures_cfg);
+                motion_sensor.update_layout(&shared_info.layout_configs)?;
+            }
+
+            let is_disconnected = process_events(
+                shared_info,
+                &impl_cfg,
+                steam_event_receiver,
+                &mut motion_sensor,
+                &mut gesture_detector,
+            )?;
+            if is_disconnected {
+                return Ok(LoopExit::Disconnected);
             }
         }
 
@@ -381,75 +490,245 @@ fn process_event_loop(
     }
 }
 
-fn wait_for_connection() -> Result<()> {
-    let mut manager = steamy_base::Manager::new()?;
+fn wait_for_connection(
+    manager: &mut Manager,
+    termination_status: &TerminationStatus,
+) -> Result<Option<steamy_base::Controller>> {
+    let mut wait_msg_is_printed = false;
 
     loop {
+        if termination_status.check() {
+            return Ok(None);
+        };
+
         match manager.open() {
-            Ok(_) => {
+            Ok(controller) => {
                 println!("Gamepad connected");
-                return Ok(());
+                return Ok(Some(controller));
             }
             Err(_) => {
-                println!("Gamepad is not connected. Waiting...");
-                sleep(Duration::from_millis(5000));
+                if !wait_msg_is_printed {
+                    wait_msg_is_printed = true;
+                    println!("Gamepad is not connected. Waiting...");
+                }
+                sleep(RECONNECT_INTERVAL);
             }
         }
     }
 }
 
 pub fn run_steamy_loop(
-    shared_info: &SharedInfo,
+    shared_info: &mut SharedInfo,
     configs: &MainConfigs,
     termination_status: &TerminationStatus,
+    process_watcher: ThreadHandleOption,
+    probed: Option<(Manager, steamy_base::Controller)>,
 ) -> Result<()> {
-    wait_for_connection()?;
+    let steamy_channel_size = configs.general.steamy_channel_size;
+
+    let (mut manager, mut probed_controller) = match probed {
+        Some((manager, controller)) => (manager, Some(controller)),
+        None => (Manager::new()?, None),
+    };
+
+    loop {
+        let controller = match probed_controller.take() {
+            Some(controller) => {
+                println!("Gamepad connected");
+                Some(controller)
+            }
+            None => wait_for_connection(&mut manager, termination_status)?,
+        };
+        let Some(mut controller) = controller else {
+            return Ok(());
+        };
+        let controller_id = controller_id(&mut controller);
 
-    let steamy_channel_size = configs.clone().general.steamy_channel_size;
-    let (steam_event_sender, steam_event_receiver) = create_channel(steamy_channel_size);
+        // New channel drops events left from the previous connection
+        let (steam_event_sender, steam_event_receiver) = create_channel(steamy_channel_size);
 
-    let mut manager: Manager = Manager::new()?;
+        #[cfg(not(feature = "steamy_use_threads"))]
+        let loop_exit = process_event_loop(
+            &mut controller,
+            shared_info,
+            configs,
+            &steam_event_sender,
+            &steam_event_receiver,
+            termination_status,
+            process_watcher,
+            controller_id.as_str(),
+        )?;
 
-    let configs_copy = configs.clone();
-    let termination_status_copy = termination_status.clone();
+        #[cfg(feature = "steamy_use_threads")]
+        let loop_exit = {
+            let configs_copy = configs.clone();
+            let termination_status_copy = termination_status.clone();
+            // Reading thread is stopped on every disconnection
+            let read_stop_status = TerminationStatus::default();
+            let read_stop_status_copy = read_stop_status.clone();
 
-    match manager.open() {
-        Ok(mut controller) => {
-            #[cfg(not(feature = "steamy_use_threads"))]
-            {
-                termination_status.check_result(process_event_loop(
+            let reading_thread = thread::spawn(move || {
+                termination_status_copy.check_result(read_events_loop(
                     &mut controller,
-                    &shared_info,
-                    &configs,
+                    &configs_copy,
                     &steam_event_sender,
-                    &steam_event_receiver,
-                    &termination_status,
+                    &read_stop_status_copy,
                 ));
+            });
+
+            let loop_exit = process_event_loop(
+                shared_info,
+                configs,
+                &steam_event_receiver,
+                termination_status,
+                process_watcher,
+                controller_id.as_str(),
+            )?;
+
+            read_stop_status.terminate()?;
+            if reading_thread.join().is_err() {
+                bail!("Reading thread panicked")
             }
+            loop_exit
+        };
 
-            #[cfg(feature = "steamy_use_threads")]
-            {
-                thread::spawn(move || {
-                    termination_status_copy.check_result(read_events_loop(
-                        &mut controller,
-                        &configs_copy,
-                        &steam_event_sender,
-                        &termination_status_copy,
-                    ));
-                });
-
-                termination_status.check_result(process_event_loop(
-                    &shared_info,
-                    &configs,
-                    &steam_event_receiver,
-                    &termination_status,
-                ));
-            }
+        if loop_exit == LoopExit::Terminated {
+            return Ok(());
         }
-        Err(_) => {
-            bail!("Cannot happen")
+        println!("Waiting for the gamepad to reconnect");
+    }
+}
+
+#[derive(Default)]
+pub struct SteamyBackend {
+    // Controller opened by 'is_available' is used by 'run' instead of opening it again
+    probed: Option<(Manager, steamy_base::Controller)>,
+}
+
+impl InputBackend for SteamyBackend {
+    fn name(&self) -> InputBackendName {
+        InputBackendName::Steamy
+    }
+
+    fn is_available(&mut self) -> bool {
+        // Controller can't be opened while it's still held from the previous check
+        self.probed = None;
+        let Ok(mut manager) = Manager::new() else {
+            return false;
+        };
+        self.probed = manager.open().ok().map(|controller| (manager, controller));
+        self.probed.is_some()
+    }
+
+    fn run(&mut self, configs: &MainConfigs, termination_status: &TerminationStatus) -> Result<()> {
+        let mut shared_info = SharedInfo::new(configs)?;
+        let probed = self.probed.take();
+        let process_watcher = spawn_process_watcher(
+            shared_info.layout_switch_sender(),
+            configs,
+            termination_status,
+        );
+
+        #[cfg(not(feature = "main_as_thread"))]
+        {
+            spawn_writing_thread(&shared_info, configs, termination_status);
+
+            run_steamy_loop(
+                &mut shared_info,
+                configs,
+                termination_status,
+                process_watcher.as_ref(),
+                probed,
+            )
         }
-    };
 
-    Ok(())
+        #[cfg(feature = "main_as_thread")]
+        {
+            let termination_status_copy = termination_status.clone();
+            let mouse_receiver = shared_info.mouse_receiver.clone();
+            let button_receiver = shared_info.button_receiver.clone();
+            let layout_receiver = shared_info.layout_receiver.clone();
+            let configs_copy = configs.clone();
+
+            thread::spawn(move || {
+                termination_status_copy.check_result(run_steamy_loop(
+                    &mut shared_info,
+                    &configs_copy,
+                    &termination_status_copy,
+                    process_watcher.as_ref(),
+                    probed,
+                ));
+            });
+
+            write_events(
+                &mouse_receiver,
+                &button_receiver,
+                &layout_receiver,
+                configs,
+                termination_status,
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output_sink::{OutputCommand, RecordingSink};
+    use crate::process_event::{MouseEvent, PadStickEvent};
+    use crate::writing_thread::EventWriter;
+    use std::path::Path;
+    use universal_input::KeyCode;
+
+    #[test]
+    fn disconnection_releases_keys_and_resets_pads() -> Result<()> {
+        let configs = MainConfigs::load(Path::new(env!("CARGO_MANIFEST_DIR")).join("config"))?;
+        let shared_info = SharedInfo::new(&configs)?;
+        let impl_cfg = ImplementationSpecificCfg::new(0.0, 1.0, false);
+        let mut event_writer =
+            EventWriter::from_layout(&shared_info.layout_configs, false, RecordingSink::new())?;
+        let step = |event_writer: &mut EventWriter<RecordingSink>| -> Result<Vec<OutputCommand>> {
+            event_writer.step(&shared_info.mouse_receiver, &shared_info.button_receiver)?;
+            event_writer.sink().take_commands()
+        };
+
+        let event = normalize_event(
+            &SteamyEvent::Button(SteamyButton::A, true),
+            shared_info.RESET_BTN,
+        )?;
+        process_event(event, &shared_info, &impl_cfg)?;
+        let mouse_sender = &shared_info.mouse_sender;
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::FingerPut))?;
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedX(0.1)))?;
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedY(0.1)))?;
+        assert_eq!(
+            step(&mut event_writer)?,
+            [OutputCommand::Pressed(KeyCode::KEY_SPACE)]
+        );
+
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedX(0.3)))?;
+        let commands = step(&mut event_writer)?;
+        assert!(
+            matches!(commands.as_slice(), [OutputCommand::MouseMoved(x, 0)] if *x > 0),
+            "{:?}",
+            commands
+        );
+
+        // Release of the button is lost with the connection. Hard release sends every key
+        shared_info.reset_controller_state()?;
+        let commands = step(&mut event_writer)?;
+        assert!(commands.contains(&OutputCommand::Released(KeyCode::KEY_SPACE)));
+        assert!(
+            commands
+                .iter()
+                .all(|command| matches!(command, OutputCommand::Released(_))),
+            "{:?}",
+            commands
+        );
+
+        // Position before the disconnection isn't used for the next move
+        mouse_sender.send(MouseEvent::RightPad(PadStickEvent::MovedX(0.5)))?;
+        assert!(step(&mut event_writer)?.is_empty());
+        Ok(())
+    }
 }
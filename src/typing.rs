@@ -0,0 +1,157 @@
+use crate::buttons_state::ButtonsState;
+use crate::chords::ChordState;
+use crate::configs::{TypingConfigs, TypingPadConfigs, TYPING_RING_ZONES};
+use crate::match_event::ButtonName;
+use crate::math_ops::{distance, Angle, ZoneAllowedRange, ZonesMapper};
+use crate::pads_ops::CoordState::Value;
+use crate::pads_ops::{CoordsHistoryState, PadsCoords};
+use ahash::AHashMap;
+use color_eyre::eyre::Result;
+use log::debug;
+use universal_input::{KeyCode, KeyCodes};
+
+// Zones cover the whole circle without gaps
+const TYPING_ZONE_RANGE: Angle = 180 / TYPING_RING_ZONES;
+
+fn ring_mapper(keys: &[KeyCodes], threshold: f32) -> Result<ZonesMapper<KeyCode>> {
+    let zone_range = ZoneAllowedRange::from_one_value(TYPING_ZONE_RANGE, true)?;
+    ZonesMapper::gen_from(keys.to_vec(), 0, &zone_range, threshold, false)
+}
+
+struct TypingPad {
+    inner: ZonesMapper<KeyCode>,
+    outer: Option<ZonesMapper<KeyCode>>,
+    outer_threshold: f32,
+    commit_btns: Vec<ButtonName>,
+}
+
+impl TypingPad {
+    fn new(pad_cfg: &TypingPadConfigs, start_threshold: f32, outer_threshold: f32) -> Result<Self> {
+        let outer = match pad_cfg.outer_keys.is_empty() {
+            true => None,
+            false => Some(ring_mapper(&pad_cfg.outer_keys, outer_threshold)?),
+        };
+        Ok(Self {
+            inner: ring_mapper(&pad_cfg.inner_keys, start_threshold)?,
+            outer,
+            outer_threshold,
+            commit_btns: pad_cfg.commit_btns.clone(),
+        })
+    }
+
+    fn key_codes_at(&mut self, pad: &CoordsHistoryState) -> Option<KeyCodes> {
+        let pos = pad.cur_pos().try_rotate(pad.finger_rotation);
+        let (Value(x), Value(y)) = (pos.x, pos.y) else {
+            return None;
+        };
+        let zone_mapper = match &mut self.outer {
+            Some(outer) if distance(x, y) > self.outer_threshold => outer,
+            _ => &mut self.inner,
+        };
+        zone_mapper.detect_zone(pos.x, pos.y).1
+    }
+
+    fn commit(&mut self, pad: &CoordsHistoryState, buttons_state: &mut ButtonsState) -> Result<()> {
+        match self.key_codes_at(pad) {
+            Some(key_codes) if !key_codes.is_empty() => {
+                debug!("Typed: {:?}", key_codes);
+                buttons_state.press_keycodes(key_codes.clone(), true)?;
+                buttons_state.release_keycodes(key_codes, true)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// On-pad keyboard used in Typing mode
+pub struct TypingState {
+    left_pad: TypingPad,
+    right_pad: TypingPad,
+    buttons_layout: AHashMap<ButtonName, KeyCodes>,
+    chord_state: Option<ChordState>,
+}
+
+impl TypingState {
+    pub fn new(typing_cfg: &TypingConfigs) -> Result<Self> {
+        let (start_threshold, outer_threshold) =
+            (typing_cfg.start_threshold, typing_cfg.outer_threshold);
+        Ok(Self {
+            left_pad: TypingPad::new(&typing_cfg.left_pad, start_threshold, outer_threshold)?,
+            right_pad: TypingPad::new(&typing_cfg.right_pad, start_threshold, outer_threshold)?,
+            buttons_layout: typing_cfg.buttons_layout.layout.clone(),
+            chord_state: typing_cfg.chords_cfg.as_ref().map(ChordState::new),
+        })
+    }
+
+    pub fn reset(&mut self) {
+        if let Some(chord_state) = &mut self.chord_state {
+            chord_state.reset();
+        }
+    }
+
+    fn layout_codes(&self, button_name: ButtonName) -> Option<KeyCodes> {
+        self.buttons_layout
+            .get(&button_name)
+            .filter(|key_codes| !key_codes.is_empty())
+            .cloned()
+    }
+
+    /// Returns false if the button isn't used for typing
+    pub fn press(
+        &mut self,
+        button_name: ButtonName,
+        pads_coords: &PadsCoords,
+        buttons_state: &mut ButtonsState,
+    ) -> Result<bool> {
+        let is_chord = match &mut self.chord_state {
+            Some(chord_state) => chord_state.press(button_name),
+            None => false,
+        };
+        if is_chord {
+            return Ok(true);
+        }
+        if self.left_pad.commit_btns.contains(&button_name) {
+            self.left_pad.commit(&pads_coords.left_pad, buttons_state)?;
+            return Ok(true);
+        }
+        if self.right_pad.commit_btns.contains(&button_name) {
+            self.right_pad
+                .commit(&pads_coords.right_pad, buttons_state)?;
+            return Ok(true);
+        }
+        match self.layout_codes(button_name) {
+            None => Ok(false),
+            Some(key_codes) => {
+                buttons_state.press_keycodes(key_codes, false)?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Returns false if the button isn't used for typing
+    pub fn release(
+        &mut self,
+        button_name: ButtonName,
+        buttons_state: &mut ButtonsState,
+    ) -> Result<bool> {
+        let is_chord = match &mut self.chord_state {
+            Some(chord_state) => chord_state.release(button_name, buttons_state)?,
+            None => false,
+        };
+        if is_chord {
+            return Ok(true);
+        }
+        if self.left_pad.commit_btns.contains(&button_name)
+            || self.right_pad.commit_btns.contains(&button_name)
+        {
+            return Ok(true);
+        }
+        match self.layout_codes(button_name) {
+            None => Ok(false),
+            Some(key_codes) => {
+                buttons_state.release_keycodes(key_codes, false)?;
+                Ok(true)
+            }
+        }
+    }
+}
@@ -48,6 +48,10 @@ impl TerminationStatus {
         Ok(())
     }
 
+    pub fn terminate(&self) -> Result<()> {
+        self._notify_all()
+    }
+
     fn _notify_and_panic(&self, err: color_eyre::eyre::Report) {
         self._notify_all().unwrap();
         panic!("{}", err);
@@ -201,6 +205,24 @@ pub fn option_to_string<T: Display>(value: Option<T>) -> String {
     }
 }
 
+// Levenshtein distance
+pub fn edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut prev_row: Vec<usize> = (0..=second.len()).collect();
+
+    for (ind1, char1) in first.chars().enumerate() {
+        let mut cur_row = vec![ind1 + 1; second.len() + 1];
+        for (ind2, char2) in second.iter().enumerate() {
+            let substitution = prev_row[ind2] + (char1 != *char2) as usize;
+            cur_row[ind2 + 1] = substitution
+                .min(prev_row[ind2 + 1] + 1)
+                .min(cur_row[ind2] + 1);
+        }
+        prev_row = cur_row;
+    }
+    prev_row[second.len()]
+}
+
 #[macro_export]
 macro_rules! err_eyre {
     ($err:expr $(,)?) => {{
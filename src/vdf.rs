@@ -0,0 +1,236 @@
+use color_eyre::eyre::{bail, OptionExt, Result};
+use std::iter::Peekable;
+use std::str::Chars;
+
+// Valve KeyValues text format. Keys can repeat, so objects keep all pairs in order
+#[derive(Clone, Debug, PartialEq)]
+pub enum VdfValue {
+    String(String),
+    Object(Vec<(String, VdfValue)>),
+}
+
+impl VdfValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VdfValue::String(value) => Some(value.as_str()),
+            VdfValue::Object(_) => None,
+        }
+    }
+
+    // Keys are case-insensitive in Steam configs
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a VdfValue> + 'a {
+        let pairs: &[(String, VdfValue)] = match self {
+            VdfValue::String(_) => &[],
+            VdfValue::Object(pairs) => pairs,
+        };
+        pairs
+            .iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&VdfValue> {
+        self.get_all(key).next()
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(|value| value.as_str())
+    }
+
+    pub fn pairs(&self) -> &[(String, VdfValue)] {
+        match self {
+            VdfValue::String(_) => &[],
+            VdfValue::Object(pairs) => pairs,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Text(String),
+    Open,
+    Close,
+}
+
+struct Tokenizer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Tokenizer<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            chars: content.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn skip_line(&mut self) {
+        for char in self.chars.by_ref() {
+            if char == '\n' {
+                self.line += 1;
+                break;
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String> {
+        let mut text = String::new();
+        loop {
+            match self.chars.next() {
+                None => bail!("Line {}: unterminated string", self.line),
+                Some('"') => return Ok(text),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some(char) => text.push(char),
+                    None => bail!("Line {}: unterminated string", self.line),
+                },
+                Some(char) => {
+                    if char == '\n' {
+                        self.line += 1;
+                    }
+                    text.push(char)
+                }
+            }
+        }
+    }
+
+    fn unquoted(&mut self, first: char) -> String {
+        let mut text = first.to_string();
+        while let Some(&char) = self.chars.peek() {
+            if char.is_whitespace() || char == '{' || char == '}' || char == '"' {
+                break;
+            }
+            text.push(char);
+            self.chars.next();
+        }
+        text
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>> {
+        while let Some(char) = self.chars.next() {
+            match char {
+                '\n' => self.line += 1,
+                char if char.is_whitespace() => {}
+                '/' if self.chars.peek() == Some(&'/') => self.skip_line(),
+                // Platform conditionals like [$WIN32] are ignored
+                '[' => {
+                    for char in self.chars.by_ref() {
+                        if char == ']' {
+                            break;
+                        }
+                    }
+                }
+                '{' => return Ok(Some(Token::Open)),
+                '}' => return Ok(Some(Token::Close)),
+                '"' => return Ok(Some(Token::Text(self.quoted()?))),
+                char => return Ok(Some(Token::Text(self.unquoted(char)))),
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn parse_object(tokenizer: &mut Tokenizer, is_root: bool) -> Result<VdfValue> {
+    let mut pairs = vec![];
+    loop {
+        let key = match tokenizer.next_token()? {
+            None if is_root => return Ok(VdfValue::Object(pairs)),
+            None => bail!("Line {}: missing '}}'", tokenizer.line),
+            Some(Token::Close) if !is_root => return Ok(VdfValue::Object(pairs)),
+            Some(Token::Text(key)) => key,
+            Some(_) => bail!("Line {}: key is expected", tokenizer.line),
+        };
+        let value = match tokenizer.next_token()?.ok_or_eyre(format!(
+            "Line {}: value of '{}' is missing",
+            tokenizer.line, key
+        ))? {
+            Token::Text(value) => VdfValue::String(value),
+            Token::Open => parse_object(tokenizer, false)?,
+            Token::Close => bail!("Line {}: value of '{}' is missing", tokenizer.line, key),
+        };
+        pairs.push((key, value));
+    }
+}
+
+pub fn parse_vdf(content: &str) -> Result<VdfValue> {
+    parse_object(&mut Tokenizer::new(content), true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(value: &str) -> VdfValue {
+        VdfValue::String(value.to_string())
+    }
+
+    #[test]
+    fn nested_objects_keep_repeated_keys() {
+        let root = parse_vdf(
+            r#"
+            "controller_mappings"
+            {
+                "group" { "id" "0" }
+                "group" { "id" "1" "inputs" { "button_a" {} } }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let mappings = root.get("Controller_Mappings").unwrap();
+        let ids: Vec<&str> = mappings
+            .get_all("group")
+            .filter_map(|group| group.get_str("id"))
+            .collect();
+        assert_eq!(ids, ["0", "1"]);
+        assert_eq!(
+            mappings.get_all("group").nth(1).unwrap().get("inputs"),
+            Some(&VdfValue::Object(vec![(
+                "button_a".to_string(),
+                VdfValue::Object(vec![])
+            )]))
+        );
+    }
+
+    #[test]
+    fn quoted_and_unquoted_strings() {
+        let root = parse_vdf(
+            r#"
+            // Comment line
+            version 3
+            "title" "Two words" [$WIN32]
+            "binding"	"key_press SPACE, Jump"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            root.pairs(),
+            [
+                ("version".to_string(), text("3")),
+                ("title".to_string(), text("Two words")),
+                ("binding".to_string(), text("key_press SPACE, Jump")),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes_in_quoted_strings() {
+        let root = parse_vdf(r#""title" "Say \"hi\"\n\tC:\\Games""#).unwrap();
+        assert_eq!(root.get_str("title"), Some("Say \"hi\"\n\tC:\\Games"));
+    }
+
+    #[test]
+    fn malformed_input_is_an_error() {
+        let err = parse_vdf("\"title\" \"unterminated").unwrap_err();
+        assert_eq!(err.to_string(), "Line 1: unterminated string");
+
+        let err = parse_vdf("\"group\"\n{\n\"id\" \"0\"\n").unwrap_err();
+        assert_eq!(err.to_string(), "Line 4: missing '}'");
+
+        let err = parse_vdf("\"group\" { \"id\" }").unwrap_err();
+        assert_eq!(err.to_string(), "Line 1: value of 'id' is missing");
+    }
+}
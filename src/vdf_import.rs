@@ -0,0 +1,387 @@
+use crate::layout_import::{is_wasd, ImportedLayout};
+use crate::match_event::ButtonName;
+use crate::vdf::{parse_vdf, VdfValue};
+use ahash::AHashMap;
+use color_eyre::eyre::{OptionExt, Result};
+use universal_input::KeyCode;
+
+const MOUSE_MODES: [&str; 4] = [
+    "absolute_mouse",
+    "mouse_joystick",
+    "mouse_region",
+    "joystick_mouse",
+];
+// Same order as in 'is_wasd'
+const DPAD_INPUTS: [&str; 4] = ["dpad_north", "dpad_east", "dpad_south", "dpad_west"];
+
+// Steam names that differ from evdev ones after removing underscores
+fn key_name_from_steam(name: &str) -> String {
+    let name = match name {
+        "RETURN" => "ENTER",
+        "ESCAPE" => "ESC",
+        "LEFT_CONTROL" => "LEFTCTRL",
+        "RIGHT_CONTROL" => "RIGHTCTRL",
+        "LEFT_WINDOWS" => "LEFTMETA",
+        "RIGHT_WINDOWS" => "RIGHTMETA",
+        "UP_ARROW" => "UP",
+        "DOWN_ARROW" => "DOWN",
+        "LEFT_ARROW" => "LEFT",
+        "RIGHT_ARROW" => "RIGHT",
+        "PERIOD" => "DOT",
+        "SINGLE_QUOTE" => "APOSTROPHE",
+        "FORWARD_SLASH" => "SLASH",
+        "DASH" => "MINUS",
+        "EQUALS" => "EQUAL",
+        "LEFT_BRACKET" => "LEFTBRACE",
+        "RIGHT_BRACKET" => "RIGHTBRACE",
+        "BACK_TICK" => "GRAVE",
+        name => name,
+    };
+    match name.strip_prefix("KEYPAD_") {
+        Some(name) => format!("KP{}", name.replace('_', "")),
+        None => name.replace('_', ""),
+    }
+}
+
+// "key_press W, Move Forward". Label after the comma is optional
+pub fn key_code_from_binding(binding: &str) -> Option<String> {
+    let binding = binding.split(',').next()?.trim();
+    let mut parts = binding.split_whitespace();
+    let (kind, name) = (parts.next()?, parts.next()?);
+
+    let code = match kind {
+        "key_press" => format!("KEY_{}", key_name_from_steam(name)),
+        "mouse_button" => format!("MOUSE_{}", name),
+        "mouse_wheel" => match name {
+            "SCROLL_UP" => "KEY_SCROLLUP".to_string(),
+            "SCROLL_DOWN" => "KEY_SCROLLDOWN".to_string(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    KeyCode::try_from(code.as_str()).ok()?;
+    Some(code)
+}
+
+fn button_from_input(source: &str, input: &str) -> Option<ButtonName> {
+    let button = match (source, input) {
+        ("button_diamond", "button_a") => ButtonName::BtnDown_SideR,
+        ("button_diamond", "button_b") => ButtonName::BtnRight_SideR,
+        ("button_diamond", "button_x") => ButtonName::BtnLeft_SideR,
+        ("button_diamond", "button_y") => ButtonName::BtnUp_SideR,
+        //
+        ("switch", "button_escape") => ButtonName::ExtraBtn_SideR,
+        ("switch", "button_menu") => ButtonName::ExtraBtn_SideL,
+        ("switch", "left_bumper") => ButtonName::UpperTrigger_SideL,
+        ("switch", "right_bumper") => ButtonName::UpperTrigger_SideR,
+        ("switch", "button_back_left") => ButtonName::Wing_SideL,
+        ("switch", "button_back_right") => ButtonName::Wing_SideR,
+        //
+        ("left_trigger", "click") => ButtonName::LowerTriggerAsBtn_SideL,
+        ("right_trigger", "click") => ButtonName::LowerTriggerAsBtn_SideR,
+        //
+        ("left_trackpad", "click") => ButtonName::PadAsBtn_SideL,
+        ("right_trackpad", "click") => ButtonName::PadAsBtn_SideR,
+        ("joystick", "click") => ButtonName::StickAsBtn,
+        //
+        ("left_trackpad", "dpad_north") => ButtonName::PadUp_SideL,
+        ("left_trackpad", "dpad_east") => ButtonName::PadRight_SideL,
+        ("left_trackpad", "dpad_south") => ButtonName::PadDown_SideL,
+        ("left_trackpad", "dpad_west") => ButtonName::PadLeft_SideL,
+        ("right_trackpad", "dpad_north") => ButtonName::PadUp_SideR,
+        ("right_trackpad", "dpad_east") => ButtonName::PadRight_SideR,
+        ("right_trackpad", "dpad_south") => ButtonName::PadDown_SideR,
+        ("right_trackpad", "dpad_west") => ButtonName::PadLeft_SideR,
+        // Stick zones press the codes of these buttons
+        ("joystick", "dpad_north") => ButtonName::BtnUp_SideL,
+        ("joystick", "dpad_east") => ButtonName::BtnRight_SideL,
+        ("joystick", "dpad_south") => ButtonName::BtnDown_SideL,
+        ("joystick", "dpad_west") => ButtonName::BtnLeft_SideL,
+        _ => return None,
+    };
+    Some(button)
+}
+
+fn is_supported_mode(source: &str, mode: &str) -> bool {
+    match source {
+        "button_diamond" => mode == "four_buttons",
+        "switch" => mode == "switches",
+        "left_trigger" | "right_trigger" => mode == "trigger",
+        "left_trackpad" => mode == "dpad" || mode == "scrollwheel",
+        "right_trackpad" => mode == "dpad" || MOUSE_MODES.contains(&mode),
+        "joystick" => mode == "dpad",
+        _ => false,
+    }
+}
+
+fn binding_codes(imported: &mut ImportedLayout, context: &str, input: &VdfValue) -> Vec<String> {
+    let mut codes = vec![];
+    let Some(activators) = input.get("activators") else {
+        return codes;
+    };
+
+    for (activator_name, activator) in activators.pairs() {
+        if !activator_name.eq_ignore_ascii_case("Full_Press") {
+            imported.skip(format!("'{}': '{}' activator", context, activator_name));
+            continue;
+        }
+        let bindings = activator
+            .get("bindings")
+            .into_iter()
+            .flat_map(|bindings| bindings.get_all("binding"))
+            .filter_map(|binding| binding.as_str());
+        for binding in bindings {
+            match key_code_from_binding(binding) {
+                Some(code) => codes.push(code),
+                None => imported.skip(format!("'{}': binding '{}'", context, binding)),
+            }
+        }
+    }
+    codes
+}
+
+fn import_group(imported: &mut ImportedLayout, source: &str, group: &VdfValue) {
+    let mode = group.get_str("mode").unwrap_or_default();
+    if !is_supported_mode(source, mode) {
+        imported.skip(format!("'{}' in '{}' mode", source, mode));
+        return;
+    }
+    if source == "left_trackpad" && mode == "scrollwheel" {
+        // Left pad scrolls in desktop mode
+        imported.gaming_mode = Some(false);
+    }
+
+    let mut input_codes: AHashMap<String, Vec<String>> = AHashMap::new();
+    for (input_name, input) in group.get("inputs").map(VdfValue::pairs).unwrap_or_default() {
+        let context = format!("{}.{}", source, input_name);
+        let codes = binding_codes(imported, context.as_str(), input);
+        input_codes.insert(input_name.to_lowercase(), codes);
+    }
+
+    // WASD on the left pad is replaced by its zones
+    if source == "left_trackpad" && mode == "dpad" {
+        let dpad_codes =
+            DPAD_INPUTS.map(|input| input_codes.get(input).cloned().unwrap_or_default());
+        if is_wasd(&dpad_codes) {
+            imported.gaming_mode = Some(true);
+            for input in DPAD_INPUTS {
+                input_codes.remove(input);
+            }
+        }
+    }
+
+    let mut input_names: Vec<String> = input_codes.keys().cloned().collect();
+    input_names.sort();
+    for input_name in input_names {
+        let codes = input_codes.remove(&input_name).unwrap_or_default();
+        if codes.is_empty() {
+            continue;
+        }
+        match button_from_input(source, input_name.as_str()) {
+            Some(button) => imported.buttons.push((button, codes)),
+            None => imported.skip(format!("'{}.{}'", source, input_name)),
+        }
+    }
+}
+
+pub fn import_vdf(content: &str) -> Result<ImportedLayout> {
+    let root = parse_vdf(content)?;
+    let mappings = root
+        .get("controller_mappings")
+        .ok_or_eyre("'controller_mappings' is not found. Not a Steam Input config")?;
+
+    let mut imported = ImportedLayout::new("Steam Input config");
+    imported.title = mappings.get_str("title").map(str::to_string);
+
+    let groups: AHashMap<&str, &VdfValue> = mappings
+        .get_all("group")
+        .filter_map(|group| Some((group.get_str("id")?, group)))
+        .collect();
+    // Only the default action set is imported
+    let preset = mappings
+        .get_all("preset")
+        .next()
+        .ok_or_eyre("Config has no presets")?;
+    let source_bindings = preset
+        .get("group_source_bindings")
+        .map(VdfValue::pairs)
+        .unwrap_or_default();
+
+    for (group_id, source_binding) in source_bindings {
+        let source_binding = source_binding.as_str().unwrap_or_default();
+        // "joystick active", "joystick inactive" or "joystick active modeshift"
+        let parts: Vec<&str> = source_binding.split_whitespace().collect();
+        let source = parts.first().copied().unwrap_or_default();
+        if parts.contains(&"modeshift") {
+            imported.skip(format!("'{}': mode shift", source));
+            continue;
+        }
+        if !parts.contains(&"active") {
+            continue;
+        }
+        match groups.get(group_id.as_str()) {
+            Some(group) => import_group(&mut imported, source, group),
+            None => imported.skip(format!("'{}': missing group {}", source, group_id)),
+        }
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configs::LayoutConfigs;
+    use crate::file_ops::yaml_filepath;
+    use std::env::temp_dir;
+    use std::fs::{copy, create_dir_all, remove_dir_all, write};
+    use std::path::Path;
+
+    const STEAM_CONFIG: &str = r#"
+"controller_mappings"
+{
+	"version"		"3"
+	"title"		"Test"
+	"group"
+	{
+		"id"		"0"
+		"mode"		"four_buttons"
+		"inputs"
+		{
+			"button_a"
+			{
+				"activators"
+				{
+					"Full_Press"
+					{
+						"bindings"
+						{
+							"binding"		"key_press SPACE, Jump"
+						}
+					}
+				}
+			}
+			"button_b"
+			{
+				"activators"
+				{
+					"Full_Press"
+					{
+						"bindings"
+						{
+							"binding"		"key_press LEFT_CONTROL"
+							"binding"		"key_press C"
+						}
+					}
+				}
+			}
+		}
+	}
+	"group"
+	{
+		"id"		"1"
+		"mode"		"dpad"
+		"inputs"
+		{
+			"dpad_north" { "activators" { "Full_Press" { "bindings" { "binding" "key_press W" } } } }
+			"dpad_east" { "activators" { "Full_Press" { "bindings" { "binding" "key_press D" } } } }
+			"dpad_south" { "activators" { "Full_Press" { "bindings" { "binding" "key_press S" } } } }
+			"dpad_west" { "activators" { "Full_Press" { "bindings" { "binding" "key_press A" } } } }
+		}
+	}
+	"group"
+	{
+		"id"		"2"
+		"mode"		"switches"
+		"inputs"
+		{
+			"button_back_left" { "activators" { "Full_Press" { "bindings" { "binding" "mouse_button RIGHT" } } } }
+			"button_menu" { "activators" { "Long_Press" { "bindings" { "binding" "key_press ESCAPE" } } } }
+		}
+	}
+	"preset"
+	{
+		"id"		"0"
+		"name"		"Default"
+		"group_source_bindings"
+		{
+			"0"		"button_diamond active"
+			"1"		"left_trackpad active"
+			"2"		"switch active"
+		}
+	}
+}
+"#;
+
+    #[test]
+    fn steam_names_are_converted() {
+        assert_eq!(
+            key_code_from_binding("key_press LEFT_CONTROL, Crouch").as_deref(),
+            Some("KEY_LEFTCTRL")
+        );
+        assert_eq!(
+            key_code_from_binding("key_press KEYPAD_PLUS").as_deref(),
+            Some("KEY_KPPLUS")
+        );
+        assert_eq!(
+            key_code_from_binding("mouse_wheel SCROLL_UP").as_deref(),
+            Some("KEY_SCROLLUP")
+        );
+        assert_eq!(
+            key_code_from_binding("controller_action CHANGE_PRESET"),
+            None
+        );
+    }
+
+    #[test]
+    fn config_is_imported() -> Result<()> {
+        let imported = import_vdf(STEAM_CONFIG)?;
+
+        assert_eq!(imported.title.as_deref(), Some("Test"));
+        assert_eq!(imported.gaming_mode, Some(true));
+        assert_eq!(
+            imported.buttons,
+            [
+                (ButtonName::BtnDown_SideR, vec!["KEY_SPACE".to_string()]),
+                (
+                    ButtonName::BtnRight_SideR,
+                    vec!["KEY_LEFTCTRL".to_string(), "KEY_C".to_string()]
+                ),
+                (ButtonName::Wing_SideL, vec!["MOUSE_RIGHT".to_string()]),
+            ]
+        );
+        assert_eq!(
+            imported.skipped,
+            ["'switch.button_menu': 'Long_Press' activator"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn imported_layout_loads() -> Result<()> {
+        let layouts_dir = temp_dir().join(format!("joystick-vdf-import-{}", std::process::id()));
+        create_dir_all(&layouts_dir)?;
+        let repo_layouts_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("config/layouts");
+        copy(
+            yaml_filepath(&repo_layouts_dir, "desktop"),
+            yaml_filepath(&layouts_dir, "desktop"),
+        )?;
+        let layout_yaml = import_vdf(STEAM_CONFIG)?.to_layout_yaml("desktop")?;
+        write(yaml_filepath(&layouts_dir, "imported"), layout_yaml)?;
+
+        let result = LayoutConfigs::load("imported", &layouts_dir);
+        remove_dir_all(&layouts_dir)?;
+        let layout_configs = result?;
+
+        assert!(layout_configs.general.gaming_mode);
+        let layout = &layout_configs.buttons_layout.layout;
+        assert_eq!(layout[&ButtonName::BtnDown_SideR][..], [KeyCode::KEY_SPACE]);
+        assert_eq!(
+            layout[&ButtonName::BtnRight_SideR][..],
+            [KeyCode::KEY_LEFTCTRL, KeyCode::KEY_C]
+        );
+        assert_eq!(layout[&ButtonName::Wing_SideL][..], [KeyCode::MOUSE_RIGHT]);
+        // Not in the Steam config, so it comes from the base layout
+        assert_eq!(layout[&ButtonName::Wing_SideR][..], [KeyCode::KEY_ENTER]);
+        Ok(())
+    }
+}
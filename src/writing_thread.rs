@@ -1,14 +1,19 @@
 use crate::buttons_state::{ButtonsState, Command};
-use crate::configs::MainConfigs;
+use crate::configs::{InputBackendName, LayoutConfigs, MainConfigs};
 use crate::exec_or_eyre;
 use crate::match_event::ButtonName;
 use crate::math_ops::{ZoneAllowedRange, ZonesMapper};
+use crate::output_sink::{InputEmulatorSink, OutputSink};
 use crate::pads_ops::CoordState::Value;
 use crate::pads_ops::{
     discard_jitter_for_pad, discard_jitter_for_stick, ConvertedCoordsDiff, Coords,
-    CoordsHistoryState, MouseMode, PadsCoords,
+    CoordsHistoryState, MouseMode, PadsCoords, TiltState,
 };
-use crate::process_event::{ButtonEvent, ButtonReceiver, MouseEvent, MouseReceiver, PadStickEvent};
+use crate::process_event::{
+    ButtonEvent, ButtonReceiver, LayoutReceiver, MouseEvent, MouseReceiver, OrientationEvent,
+    PadStickEvent, SharedInfo,
+};
+use crate::typing::TypingState;
 use crate::utils::{TerminationStatus, ThreadHandle};
 use color_eyre::eyre::{bail, Result};
 use log::debug;
@@ -16,7 +21,7 @@ use serde::{Deserialize, Serialize};
 use std::thread;
 use std::thread::{sleep, JoinHandle};
 use std::time::Instant;
-use universal_input::{EventParams, InputEmulator, KeyCode, OS_Input_Coord};
+use universal_input::{KeyCode, OS_Input_Coord};
 
 #[inline]
 fn assign_pad_event(coords_state: &mut CoordsHistoryState, pad_stick_event: PadStickEvent) {
@@ -92,100 +97,290 @@ fn assign_stick_event(
     Ok(())
 }
 
-pub fn write_events(
-    mouse_receiver: &MouseReceiver,
-    button_receiver: &ButtonReceiver,
+#[inline]
+fn assign_orientation_event(
+    coords_state: &mut CoordsHistoryState,
+    orientation_event: OrientationEvent,
+) {
+    match orientation_event {
+        OrientationEvent::Yaw(value) => {
+            assign_pad_event(coords_state, PadStickEvent::MovedX(value));
+        }
+        OrientationEvent::Pitch(value) => {
+            assign_pad_event(coords_state, PadStickEvent::MovedY(value));
+        }
+        OrientationEvent::Roll(_) => {}
+    }
+}
+
+#[inline]
+fn assign_tilt_event(
+    coords_state: &mut CoordsHistoryState,
+    tilt_state: &mut TiltState,
+    orientation_event: OrientationEvent,
+) {
+    match orientation_event {
+        OrientationEvent::Roll(value) => {
+            tilt_state.raw.x = value;
+            assign_pad_event(coords_state, PadStickEvent::MovedX(tilt_state.stick_x()));
+        }
+        OrientationEvent::Pitch(value) => {
+            tilt_state.raw.y = value;
+            assign_pad_event(coords_state, PadStickEvent::MovedY(tilt_state.stick_y()));
+        }
+        OrientationEvent::Yaw(_) => {}
+    }
+}
+
+#[inline]
+fn move_mouse<S: OutputSink>(sink: &mut S, mouse_diff: ConvertedCoordsDiff) -> Result<()> {
+    if mouse_diff.is_any_changes() {
+        sink.move_mouse(mouse_diff.x, mouse_diff.y)?;
+    }
+    Ok(())
+}
+
+pub fn spawn_writing_thread(
+    shared_info: &SharedInfo,
     configs: &MainConfigs,
     termination_status: &TerminationStatus,
-) -> Result<()> {
-    //Loading Configs
-    let writing_interval = configs.general.mouse_refresh_interval;
-    let layout_configs = &configs.layout_configs;
-    let gaming_mode = layout_configs.general.gaming_mode;
-    let scroll_cfg = layout_configs.scroll_cfg;
-    let mouse_speed = layout_configs.general.mouse_speed;
-
-    let mut pads_coords = PadsCoords::new(
-        &layout_configs.finger_rotation_cfg,
-        &layout_configs.axis_correction_cfg,
-        &layout_configs.jitter_threshold_cfg,
-    );
-
-    let mut buttons_state = ButtonsState::new(
-        layout_configs.buttons_layout.clone(),
-        layout_configs.general.repeat_keys,
-    );
-
-    //Zone Mapping
-    let WASD_zones_cfg = &layout_configs.wasd_zones_cfg;
-    let stick_zones_cfg = &layout_configs.stick_zones_cfg;
-    let _buttons_layout = &layout_configs.buttons_layout.layout;
-
-    let _wasd_zones: [Vec<KeyCode>; 4] = [
-        vec![KeyCode::KEY_W],
-        vec![KeyCode::KEY_A],
-        vec![KeyCode::KEY_S],
-        vec![KeyCode::KEY_D],
-    ];
-    let _wasd_zone_range =
-        ZoneAllowedRange::from_one_value(WASD_zones_cfg.zone_range, WASD_zones_cfg.diagonal_zones)?;
-    let mut wasd_zone_mapper = ZonesMapper::gen_from(
-        _wasd_zones.to_vec(),
-        90,
-        &_wasd_zone_range,
-        WASD_zones_cfg.start_threshold,
-        WASD_zones_cfg.diagonal_zones,
-    )?;
-
-    let _stick_zones: [Vec<KeyCode>; 4] = [
-        _buttons_layout[&ButtonName::BtnRight_SideL].clone(),
-        _buttons_layout[&ButtonName::BtnUp_SideL].clone(),
-        _buttons_layout[&ButtonName::BtnLeft_SideL].clone(),
-        _buttons_layout[&ButtonName::BtnDown_SideL].clone(),
-    ];
-    let _stick_zone_range = ZoneAllowedRange::from_one_value(
-        stick_zones_cfg.zone_range,
-        stick_zones_cfg.diagonal_zones,
-    )?;
-    let mut stick_zone_mapper = ZonesMapper::gen_from(
-        _stick_zones.to_vec(),
-        0,
-        &_stick_zone_range,
-        stick_zones_cfg.start_threshold,
-        stick_zones_cfg.diagonal_zones,
-    )?;
-    //Zone Mapping
-    //Loading Configs
-
-    let mut input_emulator = InputEmulator::new()?;
-    let mut mouse_mode = MouseMode::default();
-
-    let mut write_buffer: Vec<EventParams> = vec![];
+) -> ThreadHandle {
+    let mouse_receiver = shared_info.mouse_receiver.clone();
+    let button_receiver = shared_info.button_receiver.clone();
+    let layout_receiver = shared_info.layout_receiver.clone();
+    let configs = configs.clone();
+    let termination_status = termination_status.clone();
+
+    thread::spawn(move || {
+        termination_status.check_result(write_events(
+            &mouse_receiver,
+            &button_receiver,
+            &layout_receiver,
+            &configs,
+            &termination_status,
+        ));
+    })
+}
 
-    loop {
-        let loop_start_time = Instant::now();
+pub struct EventWriter<S: OutputSink> {
+    sink: S,
+    layout_configs: LayoutConfigs,
+    is_steamy: bool,
+
+    pads_coords: PadsCoords,
+    buttons_state: ButtonsState,
+    wasd_zone_mapper: ZonesMapper<KeyCode>,
+    stick_zone_mapper: ZonesMapper<KeyCode>,
+    tilt_state: TiltState,
+    tilt_zone_mapper: Option<ZonesMapper<KeyCode>>,
+    // Not set when only a layout is loaded
+    typing_state: Option<TypingState>,
+
+    mouse_mode: MouseMode,
+    gyro_active: bool,
+}
 
-        // println!("writing thread");
+impl<S: OutputSink> EventWriter<S> {
+    pub fn new(configs: &MainConfigs, sink: S) -> Result<Self> {
+        let is_steamy = configs.backend == InputBackendName::Steamy;
+        let mut event_writer = Self::from_layout(&configs.layout_configs, is_steamy, sink)?;
+        event_writer.typing_state = Some(TypingState::new(&configs.typing_cfg)?);
+        Ok(event_writer)
+    }
 
-        if termination_status.check() {
-            return Ok(());
+    pub fn from_layout(layout_configs: &LayoutConfigs, is_steamy: bool, sink: S) -> Result<Self> {
+        //Loading Configs
+        let gyro_cfg = layout_configs.gyro_cfg;
+
+        let pads_coords = PadsCoords::new(
+            &layout_configs.finger_rotation_cfg,
+            &layout_configs.axis_correction_cfg,
+            &layout_configs.jitter_threshold_cfg,
+            &gyro_cfg,
+        );
+
+        let buttons_state = ButtonsState::new(
+            layout_configs.buttons_layout.clone(),
+            layout_configs.general.repeat_keys,
+            layout_configs.general.action_timeouts,
+        );
+
+        //Zone Mapping
+        let WASD_zones_cfg = &layout_configs.wasd_zones_cfg;
+        let stick_zones_cfg = &layout_configs.stick_zones_cfg;
+        let _buttons_layout = &layout_configs.buttons_layout.layout;
+
+        let _wasd_zones: [Vec<KeyCode>; 4] = [
+            vec![KeyCode::KEY_W],
+            vec![KeyCode::KEY_A],
+            vec![KeyCode::KEY_S],
+            vec![KeyCode::KEY_D],
+        ];
+        let _wasd_zone_range = ZoneAllowedRange::from_one_value(
+            WASD_zones_cfg.zone_range,
+            WASD_zones_cfg.diagonal_zones,
+        )?;
+        let wasd_zone_mapper = ZonesMapper::gen_from(
+            _wasd_zones.to_vec(),
+            90,
+            &_wasd_zone_range,
+            WASD_zones_cfg.start_threshold,
+            WASD_zones_cfg.diagonal_zones,
+        )?;
+
+        let _stick_zones: [Vec<KeyCode>; 4] = [
+            _buttons_layout[&ButtonName::BtnRight_SideL].clone(),
+            _buttons_layout[&ButtonName::BtnUp_SideL].clone(),
+            _buttons_layout[&ButtonName::BtnLeft_SideL].clone(),
+            _buttons_layout[&ButtonName::BtnDown_SideL].clone(),
+        ];
+        let _stick_zone_range = ZoneAllowedRange::from_one_value(
+            stick_zones_cfg.zone_range,
+            stick_zones_cfg.diagonal_zones,
+        )?;
+        let stick_zone_mapper = ZonesMapper::gen_from(
+            _stick_zones.to_vec(),
+            0,
+            &_stick_zone_range,
+            stick_zones_cfg.start_threshold,
+            stick_zones_cfg.diagonal_zones,
+        )?;
+
+        let tilt_cfg = &layout_configs.tilt_cfg;
+        let tilt_state = TiltState::new(tilt_cfg);
+        let tilt_zone_mapper = match tilt_cfg.use_tilt {
+            false => None,
+            true => {
+                let tilt_zones_cfg = &tilt_cfg.zones;
+                let _tilt_zone_range = ZoneAllowedRange::from_one_value(
+                    tilt_zones_cfg.zone_range,
+                    tilt_zones_cfg.diagonal_zones,
+                )?;
+                Some(ZonesMapper::gen_from(
+                    tilt_cfg.zones_keys.clone(),
+                    0,
+                    &_tilt_zone_range,
+                    tilt_zones_cfg.start_threshold,
+                    tilt_zones_cfg.diagonal_zones,
+                )?)
+            }
         };
+        //Zone Mapping
+        //Loading Configs
+
+        Ok(Self {
+            sink,
+            layout_configs: layout_configs.clone(),
+            is_steamy,
+            pads_coords,
+            buttons_state,
+            wasd_zone_mapper,
+            stick_zone_mapper,
+            tilt_state,
+            tilt_zone_mapper,
+            typing_state: None,
+            mouse_mode: MouseMode::default(),
+            gyro_active: gyro_cfg.use_gyro && gyro_cfg.is_active_on_start(),
+        })
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn zone_mappers(&self) -> Vec<(&str, &ZonesMapper<KeyCode>)> {
+        let mut zone_mappers = vec![("Stick", &self.stick_zone_mapper)];
+        if self.layout_configs.general.gaming_mode {
+            zone_mappers.push(("WASD", &self.wasd_zone_mapper));
+        }
+        if let Some(tilt_zone_mapper) = &self.tilt_zone_mapper {
+            zone_mappers.push(("Tilt", tilt_zone_mapper));
+        }
+        zone_mappers
+    }
+
+    /// Releases all keys of the current layout and rebuilds the state for the new one.
+    /// Mouse mode and typing layout are kept
+    pub fn reload_layout(mut self, layout_configs: &LayoutConfigs) -> Result<Self> {
+        self.buttons_state.release_all_hard()?;
+        self.send_button_commands()?;
+        self.sink.flush()?;
+
+        let mouse_mode = self.mouse_mode;
+        let typing_state = self.typing_state;
+        let mut event_writer = Self::from_layout(layout_configs, self.is_steamy, self.sink)?;
+        event_writer.mouse_mode = mouse_mode;
+        event_writer.typing_state = typing_state;
+        Ok(event_writer)
+    }
+
+    fn send_button_commands(&mut self) -> Result<()> {
+        for command in &self.buttons_state.queue {
+            match command {
+                Command::Pressed(key_code) => {
+                    // println!("Send Pressed: {}", key_code);
+                    self.sink.press(*key_code)?;
+                }
+                Command::Released(key_code) => {
+                    // println!("Send Released: {}", key_code);
+                    self.sink.release(*key_code)?;
+                }
+            }
+        }
+
+        self.buttons_state.queue.clear();
+        Ok(())
+    }
+
+    /// Handles all pending events. Called once per writing interval
+    pub fn step(
+        &mut self,
+        mouse_receiver: &MouseReceiver,
+        button_receiver: &ButtonReceiver,
+    ) -> Result<()> {
+        let layout_configs = &self.layout_configs;
+        let gaming_mode = layout_configs.general.gaming_mode;
+        let scroll_cfg = layout_configs.scroll_cfg;
+        let mouse_speed = layout_configs.general.mouse_speed;
+        let gyro_cfg = layout_configs.gyro_cfg;
+        let tilt_cfg = &layout_configs.tilt_cfg;
+        let WASD_zones_cfg = &layout_configs.wasd_zones_cfg;
+        let stick_zones_cfg = &layout_configs.stick_zones_cfg;
+        let buttons_layout = &layout_configs.buttons_layout;
+
+        let sink = &mut self.sink;
+        let pads_coords = &mut self.pads_coords;
+        let buttons_state = &mut self.buttons_state;
+        let tilt_state = &mut self.tilt_state;
+        let mouse_mode = &mut self.mouse_mode;
+        let gyro_active = &mut self.gyro_active;
+        let typing_state = &mut self.typing_state;
 
         //MOUSE
         for event in mouse_receiver.try_iter() {
             //TODO: test try_recv_realtime. fallback: try_recv()
             // while let Some(event) = mouse_receiver.try_recv()? {
             match event {
-                MouseEvent::ModeSwitched => match mouse_mode {
-                    MouseMode::CursorMove => {
-                        mouse_mode = MouseMode::Typing;
+                MouseEvent::ModeSwitched => {
+                    // Keys held in one mode could never be released in the other
+                    buttons_state.release_all_soft()?;
+                    if let Some(typing_state) = typing_state.as_mut() {
+                        typing_state.reset();
                     }
-                    MouseMode::Typing => {
-                        mouse_mode = MouseMode::CursorMove;
+                    match mouse_mode {
+                        MouseMode::CursorMove => {
+                            *mouse_mode = MouseMode::Typing;
+                        }
+                        MouseMode::Typing => {
+                            *mouse_mode = MouseMode::CursorMove;
+                        }
                     }
-                },
+                }
                 MouseEvent::Reset => {
-                    mouse_mode = MouseMode::default();
+                    *mouse_mode = MouseMode::default();
+                    pads_coords.reset_all();
+                }
+                MouseEvent::ControllerDisconnected => {
                     pads_coords.reset_all();
                 }
                 MouseEvent::LeftPad(pad_stick_event) => {
@@ -197,74 +392,88 @@ pub fn write_events(
                 MouseEvent::Stick(pad_stick_event) => {
                     assign_stick_event(&mut pads_coords.stick, pad_stick_event)?;
                 }
+                MouseEvent::Orientation(orientation_event) => {
+                    if *gyro_active {
+                        assign_orientation_event(&mut pads_coords.gyro, orientation_event);
+                    }
+                    if tilt_cfg.use_tilt {
+                        assign_tilt_event(&mut pads_coords.tilt, tilt_state, orientation_event);
+                    }
+                }
+                MouseEvent::TiltRecentered => {
+                    tilt_state.recenter();
+                }
+                MouseEvent::GyroActive(is_active) => {
+                    // Triggers send repeated presses while held
+                    if *gyro_active != is_active {
+                        *gyro_active = is_active;
+                        // Start from the current orientation to avoid cursor jumps
+                        pads_coords.gyro.reset_all();
+                    }
+                }
             }
         }
 
-        #[cfg(all(feature = "use_steamy", feature = "use_only_last_coords"))]
+        // Don't use for gilrs. Cursor will jump around
+        #[cfg(feature = "use_only_last_coords")]
         {
-            pads_coords.left_pad.cur.x = discard_jitter_for_pad(
-                pads_coords.left_pad.prev.x,
-                pads_coords.left_pad.new_x,
-                pads_coords.left_pad.jitter_threshold,
-            );
-            pads_coords.left_pad.cur.y = discard_jitter_for_pad(
-                pads_coords.left_pad.prev.y,
-                pads_coords.left_pad.new_y,
-                pads_coords.left_pad.jitter_threshold,
-            );
-            pads_coords.right_pad.cur.x = discard_jitter_for_pad(
-                pads_coords.right_pad.prev.x,
-                pads_coords.right_pad.new_x,
-                pads_coords.right_pad.jitter_threshold,
-            );
-            pads_coords.right_pad.cur.y = discard_jitter_for_pad(
-                pads_coords.right_pad.prev.y,
-                pads_coords.right_pad.new_y,
-                pads_coords.right_pad.jitter_threshold,
-            );
+            if self.is_steamy {
+                pads_coords.left_pad.cur.x = discard_jitter_for_pad(
+                    pads_coords.left_pad.prev.x,
+                    pads_coords.left_pad.new_x,
+                    pads_coords.left_pad.jitter_threshold,
+                );
+                pads_coords.left_pad.cur.y = discard_jitter_for_pad(
+                    pads_coords.left_pad.prev.y,
+                    pads_coords.left_pad.new_y,
+                    pads_coords.left_pad.jitter_threshold,
+                );
+                pads_coords.right_pad.cur.x = discard_jitter_for_pad(
+                    pads_coords.right_pad.prev.x,
+                    pads_coords.right_pad.new_x,
+                    pads_coords.right_pad.jitter_threshold,
+                );
+                pads_coords.right_pad.cur.y = discard_jitter_for_pad(
+                    pads_coords.right_pad.prev.y,
+                    pads_coords.right_pad.new_y,
+                    pads_coords.right_pad.jitter_threshold,
+                );
+            }
         }
 
         // pads_coords.set_prev_if_cur_is_none();
 
         pads_coords.stick.send_commands_diff(
-            &mut stick_zone_mapper,
-            &stick_zones_cfg,
-            &mut buttons_state,
+            &mut self.stick_zone_mapper,
+            stick_zones_cfg,
+            buttons_state,
         )?;
 
-        if mouse_mode != MouseMode::Typing {
+        if let Some(tilt_zone_mapper) = &mut self.tilt_zone_mapper {
+            pads_coords.tilt.send_commands_diff(
+                tilt_zone_mapper,
+                &tilt_cfg.zones,
+                buttons_state,
+            )?;
+        }
+
+        if *mouse_mode != MouseMode::Typing {
             if pads_coords.right_pad.any_changes() {
                 let mouse_diff = pads_coords.right_pad.diff();
                 let mouse_diff = mouse_diff.convert(mouse_speed);
-                if mouse_diff.is_any_changes() {
-                    #[cfg(feature = "gradual_mouse")]
-                    {
-                        // println!("Gradual Mouse");
-                        #[cfg(feature = "use_buffered_input")]
-                        {
-                            write_buffer.extend(
-                                input_emulator
-                                    .buffered_gradual_move_mouse(mouse_diff.x, mouse_diff.y),
-                            );
-                        }
-                        #[cfg(not(feature = "use_buffered_input"))]
-                        {
-                            #[cfg(feature = "use_raw_input")]
-                            {
-                                input_emulator
-                                    .gradual_move_mouse_raw(mouse_diff.x, mouse_diff.y)?;
-                            }
-                            #[cfg(not(feature = "use_raw_input"))]
-                            {
-                                input_emulator.gradual_move_mouse(mouse_diff.x, mouse_diff.y)?;
-                            }
-                        }
-                    }
-                    #[cfg(not(feature = "gradual_mouse"))]
-                    {
-                        input_emulator.move_mouse(mouse_diff.x, mouse_diff.y)?;
-                    }
+                move_mouse(sink, mouse_diff)?;
+            }
+            if *gyro_active && pads_coords.gyro.any_changes() {
+                let mut gyro_diff = pads_coords.gyro.diff().wrap_around();
+                if gyro_cfg.invert_x {
+                    gyro_diff.x = -gyro_diff.x;
+                }
+                if gyro_cfg.invert_y {
+                    gyro_diff.y = -gyro_diff.y;
                 }
+                let gyro_diff =
+                    gyro_diff.convert_per_axis(gyro_cfg.x_sensitivity, gyro_cfg.y_sensitivity);
+                move_mouse(sink, gyro_diff)?;
             }
             match gaming_mode {
                 false => {
@@ -278,47 +487,15 @@ pub fn write_events(
 
                         let scroll_diff = scroll_diff.convert(scroll_cfg.speed);
                         if scroll_diff.is_any_changes() {
-                            #[cfg(feature = "gradual_scroll")]
-                            {
-                                // println!("Gradual Scroll");
-                                #[cfg(feature = "use_buffered_input")]
-                                {
-                                    write_buffer.extend(
-                                        input_emulator
-                                            .buffered_gradual_scroll(scroll_diff.x, scroll_diff.y),
-                                    );
-                                }
-                                #[cfg(not(feature = "use_buffered_input"))]
-                                {
-                                    #[cfg(feature = "use_raw_input")]
-                                    {
-                                        input_emulator
-                                            .gradual_scroll_raw(scroll_diff.x, scroll_diff.y)?;
-                                    }
-                                    #[cfg(not(feature = "use_raw_input"))]
-                                    {
-                                        input_emulator
-                                            .gradual_scroll(scroll_diff.x, scroll_diff.y)?;
-                                    }
-                                }
-                            }
-                            #[cfg(not(feature = "gradual_scroll"))]
-                            {
-                                if scroll_diff.x != 0 {
-                                    input_emulator.scroll_x(scroll_diff.x)?;
-                                }
-                                if scroll_diff.y != 0 {
-                                    input_emulator.scroll_y(scroll_diff.y)?;
-                                }
-                            }
+                            sink.scroll(scroll_diff.x, scroll_diff.y)?;
                         }
                     }
                 }
                 true => {
                     pads_coords.left_pad.send_commands_diff(
-                        &mut wasd_zone_mapper,
-                        &WASD_zones_cfg,
-                        &mut buttons_state,
+                        &mut self.wasd_zone_mapper,
+                        WASD_zones_cfg,
+                        buttons_state,
                     )?;
                 }
             }
@@ -330,58 +507,80 @@ pub fn write_events(
         pads_coords.update();
         pads_coords.reset_current();
 
+        buttons_state.update_actions()?;
+
         //BUTTONS
         for event in button_receiver.try_iter() {
             //TODO: test try_recv_realtime. fallback: try_recv()
             // while let Some(event) = button_receiver.try_recv()? {
+            // Special buttons keep working while typing
+            let typing_state = match typing_state.as_mut() {
+                Some(typing_state)
+                    if *mouse_mode == MouseMode::Typing
+                        && event.button_name() != buttons_layout.reset_btn
+                        && event.button_name() != buttons_layout.switch_mode_btn =>
+                {
+                    Some(typing_state)
+                }
+                _ => None,
+            };
             match event {
                 //Press goes first to check if already pressed
                 ButtonEvent::Pressed(button_name) => {
-                    buttons_state.press(button_name, false)?;
-                }
-                ButtonEvent::Released(button_name) => {
-                    buttons_state.release(button_name)?;
-                }
-            }
-        }
-
-        #[cfg(feature = "use_buffered_input")]
-        {
-            for command in &buttons_state.queue {
-                match command {
-                    Command::Pressed(key_code) => {
-                        // println!("Send Pressed: {}", key_code);
-                        write_buffer.extend(input_emulator.buffered_press(*key_code)?);
-                    }
-                    Command::Released(key_code) => {
-                        // println!("Send Released: {}", key_code);
-                        write_buffer.extend(input_emulator.buffered_release(*key_code)?);
+                    let is_typed = match typing_state {
+                        Some(typing_state) => {
+                            typing_state.press(button_name, pads_coords, buttons_state)?
+                        }
+                        None => false,
+                    };
+                    if !is_typed {
+                        buttons_state.press(button_name, false)?;
                     }
                 }
-            }
-        }
-        #[cfg(not(feature = "use_buffered_input"))]
-        {
-            for command in &buttons_state.queue {
-                match command {
-                    Command::Pressed(key_code) => {
-                        input_emulator.press(*key_code)?;
-                    }
-                    Command::Released(key_code) => {
-                        input_emulator.release(*key_code)?;
+                ButtonEvent::Released(button_name) => {
+                    let is_typed = match typing_state {
+                        Some(typing_state) => typing_state.release(button_name, buttons_state)?,
+                        None => false,
+                    };
+                    if !is_typed {
+                        buttons_state.release(button_name)?;
                     }
                 }
             }
         }
 
-        buttons_state.queue.clear();
+        self.send_button_commands()?;
 
-        #[cfg(feature = "use_buffered_input")]
-        {
-            input_emulator.write_buffer(&write_buffer)?;
-            write_buffer.clear()
+        self.sink.flush()
+    }
+}
+
+pub fn write_events(
+    mouse_receiver: &MouseReceiver,
+    button_receiver: &ButtonReceiver,
+    layout_receiver: &LayoutReceiver,
+    configs: &MainConfigs,
+    termination_status: &TerminationStatus,
+) -> Result<()> {
+    let writing_interval = configs.general.mouse_refresh_interval;
+
+    let mut event_writer = EventWriter::new(configs, InputEmulatorSink::new()?)?;
+
+    loop {
+        let loop_start_time = Instant::now();
+
+        // println!("writing thread");
+
+        if termination_status.check() {
+            return Ok(());
+        };
+
+        for layout_configs in layout_receiver.try_iter() {
+            event_writer = event_writer.reload_layout(&layout_configs)?;
         }
 
+        event_writer.step(mouse_receiver, button_receiver)?;
+
         //Scheduler
         let loop_iteration_runtime = loop_start_time.elapsed();
 